@@ -0,0 +1,14 @@
+CREATE TABLE saved_searches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR NOT NULL,
+    owner VARCHAR NOT NULL,
+    shared BOOLEAN NOT NULL,
+    search VARCHAR NOT NULL,
+    order_table VARCHAR,
+    ascending BOOLEAN NOT NULL,
+    visible_columns VARCHAR,
+    creation_date DATETIME NOT NULL,
+    last_updated DATETIME NOT NULL,
+
+    UNIQUE(name, owner)
+);
@@ -0,0 +1,2 @@
+DROP TABLE copy_session_items;
+DROP TABLE copy_sessions;
@@ -0,0 +1,13 @@
+CREATE TABLE copy_sessions (
+    session_id VARCHAR PRIMARY KEY NOT NULL,
+    producer VARCHAR,
+    disconnected DATETIME
+);
+
+CREATE TABLE copy_session_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id VARCHAR NOT NULL,
+    data VARCHAR NOT NULL
+);
+
+CREATE INDEX copy_session_items_session_id ON copy_session_items(session_id);
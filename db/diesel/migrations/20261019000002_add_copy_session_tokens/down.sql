@@ -0,0 +1,3 @@
+ALTER TABLE copy_sessions DROP COLUMN last_active;
+ALTER TABLE copy_sessions DROP COLUMN build_location;
+ALTER TABLE copy_sessions DROP COLUMN token;
@@ -0,0 +1,7 @@
+ALTER TABLE copy_sessions ADD COLUMN token VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE copy_sessions ADD COLUMN build_location VARCHAR;
+ALTER TABLE copy_sessions ADD COLUMN last_active DATETIME;
+
+-- sessions opened before tokens existed get one nobody was handed, so their queues stay
+-- visible to the admin endpoints until they expire like any other session
+UPDATE copy_sessions SET token = lower(hex(randomblob(16))) WHERE token = '';
@@ -0,0 +1 @@
+ALTER TABLE qc_forms DROP COLUMN qc_answer_details;
@@ -0,0 +1,26 @@
+ALTER TABLE qc_forms ADD COLUMN qc_answer_details VARCHAR NOT NULL DEFAULT '{}';
+
+-- existing answers get the initials of their pass, when they were given is unknown
+
+UPDATE qc_forms SET qc_answer_details = COALESCE((
+    WITH RECURSIVE entries(entry, rest) AS (
+        SELECT NULL, qc_forms.qc_answers
+        UNION ALL
+        SELECT substr(rest, 1, instr(rest || ',', ',') - 1), substr(rest, instr(rest || ',', ',') + 1)
+        FROM entries WHERE rest <> ''
+    )
+    SELECT json_group_object(
+        substr(entry, 1, instr(entry, ':') - 1),
+        json_array(
+            json_object(
+                'answer', lower(substr(entry, instr(entry, ':') + 1, 1)),
+                'answered_by', CASE WHEN lower(substr(entry, instr(entry, ':') + 1, 1)) = 'i' THEN NULL ELSE qc_forms.qc1_initial END
+            ),
+            json_object(
+                'answer', lower(substr(entry, instr(entry, ':') + 2, 1)),
+                'answered_by', CASE WHEN lower(substr(entry, instr(entry, ':') + 2, 1)) = 'i' THEN NULL ELSE qc_forms.qc2_initial END
+            )
+        )
+    )
+    FROM entries WHERE entry LIKE '%:%'
+), '{}');
@@ -0,0 +1,2 @@
+ALTER TABLE qc_forms DROP COLUMN checklist_version;
+DROP TABLE checklist_versions;
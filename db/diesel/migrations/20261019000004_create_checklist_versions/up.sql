@@ -0,0 +1,8 @@
+CREATE TABLE checklist_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    definition VARCHAR NOT NULL UNIQUE,
+    creation_date DATETIME NOT NULL
+);
+
+-- forms from before versioning are pinned to the checklist in the config on the next start
+ALTER TABLE qc_forms ADD COLUMN checklist_version INTEGER REFERENCES checklist_versions(id);
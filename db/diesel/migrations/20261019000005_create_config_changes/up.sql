@@ -0,0 +1,6 @@
+-- edits made through the admin api, replayed in order on top of the config file
+CREATE TABLE config_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    change VARCHAR NOT NULL,
+    creation_date DATETIME NOT NULL
+);
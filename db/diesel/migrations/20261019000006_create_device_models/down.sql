@@ -0,0 +1,2 @@
+DROP TABLE device_model_spellings;
+DROP TABLE device_models;
@@ -0,0 +1,21 @@
+-- the catalog `make_model` is normalized against, the defaults are enumeration keys
+CREATE TABLE device_models (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    manufacturer VARCHAR NOT NULL,
+    model VARCHAR NOT NULL,
+    -- the name with everything but letters and digits left out, lowercased
+    match_key VARCHAR NOT NULL UNIQUE,
+    form_factor VARCHAR,
+    build_type VARCHAR,
+    drive_type VARCHAR,
+    drive_size VARCHAR,
+    ram_type VARCHAR,
+    ram_size VARCHAR,
+    creation_date DATETIME NOT NULL
+);
+
+-- free text spellings an admin merged into a catalog entry
+CREATE TABLE device_model_spellings (
+    match_key VARCHAR PRIMARY KEY NOT NULL,
+    device_model INTEGER NOT NULL REFERENCES device_models(id) ON DELETE CASCADE
+);
@@ -0,0 +1,12 @@
+-- forms link to an order through their `sales_order`, which is the order number here
+CREATE TABLE sales_orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sales_order VARCHAR NOT NULL UNIQUE,
+    customer VARCHAR NOT NULL,
+    expected_units INTEGER NOT NULL,
+    due_date DATETIME,
+    -- open, in_progress, complete or cancelled
+    status VARCHAR NOT NULL,
+    creation_date DATETIME NOT NULL,
+    last_updated DATETIME NOT NULL
+);
@@ -0,0 +1 @@
+DROP TABLE unit_events;
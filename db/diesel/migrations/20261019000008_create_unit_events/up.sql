@@ -0,0 +1,15 @@
+-- where a unit went after it was built, recorded without touching the form itself
+CREATE TABLE unit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    form_id INTEGER NOT NULL REFERENCES qc_forms(id) ON DELETE CASCADE,
+    -- shelved, shipped or returned
+    event VARCHAR NOT NULL,
+    location VARCHAR,
+    carrier VARCHAR,
+    tracking_number VARCHAR,
+    notes VARCHAR NOT NULL,
+    recorded_by VARCHAR NOT NULL,
+    event_date DATETIME NOT NULL
+);
+
+CREATE INDEX unit_events_form_id ON unit_events(form_id);
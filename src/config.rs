@@ -0,0 +1,915 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+use rocket::fairing::AdHoc;
+use rocket::figment::value::magic::RelativePathBuf;
+use rocket::request::FromRequest;
+use serde::de::{DeserializeOwned, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::qc_checklist;
+
+/// The config as written in `config.json5`, templates get it as is while the
+/// typed view of it is available through `Config::typed`
+#[derive(Debug)]
+pub struct Config(pub Value, Derived);
+
+/// What a `Config` works out from its items the first time it is needed. The items
+/// aren't read again afterwards so a changed config has to be a new `Config`
+#[derive(Debug, Default)]
+struct Derived {
+    typed: OnceLock<Option<ConfigModel>>,
+    // the config without overrides and the one of each location that has them
+    located: OnceLock<(Box<Config>, BTreeMap<String, Config>)>,
+}
+
+/// Everything wrong with a config, reported together so one edit can fix them all
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("{}", .0.join("\n"))]
+pub struct ConfigProblems(pub Vec<String>);
+
+impl Config {
+    pub fn new(items: Value) -> Self {
+        Self(items, Derived::default())
+    }
+
+    /// Parses the file without validating it, for tests that change the config afterwards
+    #[cfg(test)]
+    pub(crate) fn load_from_file(
+        path: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(Self::new(json5::from_str(&contents)?))
+    }
+
+    /// Parses and validates the file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigProblems> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| ConfigProblems(vec![format!("{}: {err}", path.display())]))?;
+        let keys = json5::from_str::<KeyTree>(&contents)
+            .map_err(|err| ConfigProblems(vec![format!("{}: {err}", path.display())]))?;
+
+        let mut problems = Vec::new();
+        keys.duplicate_keys("", &mut problems);
+
+        let config = json5::from_str(&contents)
+            .map(Self::new)
+            .map_err(|err| ConfigProblems(vec![format!("{}: {err}", path.display())]))?;
+        match config.model() {
+            // kept so lookups don't parse the config again
+            Ok(model) => _ = config.1.typed.set(Some(model)),
+            Err(ConfigProblems(more)) => problems.extend(more),
+        }
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigProblems(problems))
+        }
+    }
+
+    /// The config as seen by forms built at `location`, its `location_overrides` are merged
+    /// over the rest like a JSON merge patch: objects are merged key by key, anything else
+    /// is replaced and `null` removes the key
+    pub fn for_location(&self, location: &str) -> &Config {
+        let (base, located) = self.1.located.get_or_init(|| {
+            let mut items = self.0.clone();
+            let overrides = items
+                .as_object_mut()
+                .and_then(|items| items.remove("location_overrides"));
+            let located = overrides
+                .iter()
+                .filter_map(Value::as_object)
+                .flatten()
+                .map(|(location, overrides)| {
+                    let mut items = items.clone();
+                    merge_patch(&mut items, overrides);
+                    (location.clone(), Config::new(items))
+                })
+                .collect();
+            (Box::new(Config::new(items)), located)
+        });
+        located.get(location).unwrap_or(base)
+    }
+
+    /// The typed config, parsed once and `None` if it doesn't parse. Unlike `model` the
+    /// parts that depend on each other aren't checked, which `load` already did
+    pub fn typed(&self) -> Option<&ConfigModel> {
+        self.1
+            .typed
+            .get_or_init(|| ConfigModel::parse(&self.0, &mut Vec::new()))
+            .as_ref()
+    }
+
+    /// One enumeration of the typed config like `ram_sizes`
+    pub fn enumeration(&self, name: &str) -> Option<&Enumeration> {
+        let model = self.typed()?;
+        Some(match name {
+            "build_locations" => &model.build_locations,
+            "build_types" => &model.build_types,
+            "operating_systems" => &model.operating_systems,
+            "processor_types" => &model.processor_types,
+            "ram_types" => &model.ram_types,
+            "ram_sizes" => &model.ram_sizes,
+            "processor_gens" => &model.processor_gens,
+            "drive_types" => &model.drive_types,
+            "drive_sizes" => &model.drive_sizes,
+            _ => return None,
+        })
+    }
+
+    /// One question of `qc_checks.questions`
+    pub fn question(&self, question: &str) -> Option<&Question> {
+        self.typed()?.qc_checks.questions.get(question)
+    }
+
+    /// The pattern a form field is checked against, fields without one are `None`
+    pub fn pattern(&self, field: &str) -> Option<&FieldPattern> {
+        let model = self.typed()?;
+        Some(match field {
+            "qc1_initial" | "qc2_initial" => &model.initials,
+            "sales_order" => &model.sales_order,
+            "oem_serial" => &model.oem_serial,
+            "make_model" => &model.make_model,
+            "item_serial" => &model.item_serial,
+            "asm_serial" => &model.asm_serial,
+            _ => return None,
+        })
+    }
+
+    /// The sections of `qc_checks.tech_form` in the order they are asked
+    pub fn tech_form(&self) -> &[Section] {
+        self.typed()
+            .map_or(&[], |model| model.qc_checks.tech_form.as_slice())
+    }
+
+    /// The typed config, fails with every problem found instead of the first one
+    pub fn model(&self) -> Result<ConfigModel, ConfigProblems> {
+        let mut problems = Vec::new();
+        let model = ConfigModel::parse(&self.0, &mut problems);
+        if let Some(model) = &model {
+            model.check(self, &mut problems);
+        }
+        match model {
+            Some(model) if problems.is_empty() => Ok(model),
+            _ => Err(ConfigProblems(problems)),
+        }
+    }
+}
+
+/// A form field the tech fills in, checked against `pattern` in the browser
+#[derive(Debug, Clone, Deserialize)]
+pub struct FieldPattern {
+    #[serde(default)]
+    pub title: Option<String>,
+    pub pattern: String,
+}
+
+/// The values a field can take, shown in the order of `order`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Enumeration {
+    #[serde(default)]
+    pub title: Option<String>,
+    pub order: Vec<String>,
+    pub values: BTreeMap<String, EnumerationValue>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnumerationValue {
+    pub name: String,
+    // left out of the form but still accepted in searches and old forms
+    #[serde(default)]
+    pub hidden: bool,
+    // needed by `ram_sizes` and `drive_sizes` so sizes can be compared
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+    // build types only, see `qc_checklist::pass_count`
+    #[serde(default)]
+    pub passes: Option<usize>,
+    // choices of a question only
+    #[serde(default)]
+    pub fails: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuestionKind {
+    #[default]
+    Check,
+    Number,
+    Choice,
+    Text,
+}
+
+/// One entry of `qc_checks.questions`, see `Question::grade` for how its values are graded
+#[derive(Debug, Clone, Deserialize)]
+pub struct Question {
+    pub question: String,
+    #[serde(rename = "type", default)]
+    pub kind: QuestionKind,
+    #[serde(default)]
+    pub passes: Option<usize>,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub whitelist_build_types: Option<Vec<String>>,
+    #[serde(default)]
+    pub applies_when: Option<String>,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub choices: Option<Enumeration>,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Section {
+    pub heading: String,
+    pub questions: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QcChecks {
+    // the section as written, which is what checklist versions store
+    pub definition: String,
+    pub passes: Option<usize>,
+    pub questions: BTreeMap<String, Question>,
+    pub tech_form: Vec<Section>,
+    // the printable uses `tech_form` when this is left out
+    pub pdf_form: Option<Vec<Section>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatabaseColumn {
+    pub name: String,
+    #[serde(default)]
+    pub show: bool,
+    #[serde(default)]
+    pub db_column: bool,
+    #[serde(default)]
+    pub mapping: Option<String>,
+}
+
+/// The columns of the database view
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatabaseView {
+    pub columns: BTreeMap<String, DatabaseColumn>,
+    pub order: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigModel {
+    pub initials: FieldPattern,
+    pub sales_order: FieldPattern,
+    pub oem_serial: FieldPattern,
+    pub make_model: FieldPattern,
+    pub item_serial: FieldPattern,
+    pub asm_serial: FieldPattern,
+    pub build_locations: Enumeration,
+    pub build_types: Enumeration,
+    pub operating_systems: Enumeration,
+    pub processor_types: Enumeration,
+    pub ram_types: Enumeration,
+    pub ram_sizes: Enumeration,
+    pub processor_gens: Enumeration,
+    pub drive_types: Enumeration,
+    pub drive_sizes: Enumeration,
+    pub qc_checks: QcChecks,
+    pub database: DatabaseView,
+    pub location_overrides: LocationOverrides,
+}
+
+/// Partial configs for single build locations, keyed by location, see `Config::for_location`
+pub type LocationOverrides = BTreeMap<String, Value>;
+
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let target = target.as_object_mut().expect("made an object above");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Checks a part of the config that doesn't depend on the rest of it, done as soon
+/// as the part is parsed so a broken part elsewhere doesn't hide its problems
+trait Validate {
+    fn validate(&self, path: &str, problems: &mut Vec<String>);
+}
+
+fn parse<T: DeserializeOwned + Validate>(
+    value: &Value,
+    path: &str,
+    problems: &mut Vec<String>,
+) -> Option<T> {
+    if value.is_null() {
+        problems.push(format!("{path} is missing"));
+        return None;
+    }
+    let parsed = T::deserialize(value)
+        .map_err(|err| problems.push(format!("{path}: {err}")))
+        .ok()?;
+    parsed.validate(path, problems);
+    Some(parsed)
+}
+
+/// Like `parse` but a missing value is fine
+fn parse_optional<T: DeserializeOwned + Validate>(
+    value: &Value,
+    path: &str,
+    problems: &mut Vec<String>,
+) -> Option<Option<T>> {
+    match value {
+        Value::Null => Some(None),
+        value => parse(value, path, problems).map(Some),
+    }
+}
+
+/// Parses every field on its own so each broken one is reported, returns from the
+/// enclosing function unless all of them parsed. `$parsed` fields are already parsed
+macro_rules! parse_fields {
+    ($raw:expr, $problems:expr, $ty:ident { $($field:ident),* $(,)? } $(, $parsed:ident)*) => {{
+        $(let $field = parse(&$raw[stringify!($field)], stringify!($field), $problems);)*
+        $ty { $($field: $field?,)* $($parsed: $parsed?,)* }
+    }};
+}
+
+impl Validate for usize {
+    fn validate(&self, path: &str, problems: &mut Vec<String>) {
+        if *self == 0 {
+            problems.push(format!("{path} has to be at least 1"));
+        }
+    }
+}
+
+impl FieldPattern {
+    /// The browser matches the whole value against the pattern
+    fn regex(&self) -> Result<regex::Regex, regex::Error> {
+        regex::Regex::new(&format!("^(?:{})$", self.pattern))
+    }
+
+    /// Same semantics as the html `pattern` attribute, empty values are left alone
+    pub fn matches(&self, value: &str) -> bool {
+        // the config makes sure the patterns compile when it is loaded
+        value.is_empty() || self.regex().map_or(true, |regex| regex.is_match(value))
+    }
+}
+
+impl Validate for FieldPattern {
+    fn validate(&self, path: &str, problems: &mut Vec<String>) {
+        if let Err(err) = self.regex() {
+            problems.push(format!("{path}.pattern is not a valid regex: {err}"));
+        }
+    }
+}
+
+impl Validate for Enumeration {
+    fn validate(&self, path: &str, problems: &mut Vec<String>) {
+        for key in duplicates(&self.order) {
+            problems.push(format!("{path}.order lists '{key}' more than once"));
+        }
+        for key in self.order.iter() {
+            if !self.values.contains_key(key) {
+                problems.push(format!("{path}.order lists '{key}' which is not in values"));
+            }
+        }
+        let sized = matches!(path, "ram_sizes" | "drive_sizes");
+        for (key, value) in &self.values {
+            if sized && value.size_bytes.is_none() {
+                problems.push(format!("{path}.values.{key} is missing size_bytes"));
+            }
+            if let Some(passes) = value.passes {
+                passes.validate(&format!("{path}.values.{key}.passes"), problems);
+            }
+        }
+    }
+}
+
+impl Validate for Question {
+    fn validate(&self, path: &str, problems: &mut Vec<String>) {
+        if let Some(passes) = self.passes {
+            passes.validate(&format!("{path}.passes"), problems);
+        }
+        match self.kind {
+            QuestionKind::Number => {
+                if let (Some(min), Some(max)) = (self.min, self.max) {
+                    if min > max {
+                        problems.push(format!("{path} has a min above its max"));
+                    }
+                }
+            }
+            QuestionKind::Choice => match &self.choices {
+                Some(choices) => choices.validate(&format!("{path}.choices"), problems),
+                None => problems.push(format!("{path} is a choice without choices")),
+            },
+            QuestionKind::Check | QuestionKind::Text => {}
+        }
+    }
+}
+
+impl Validate for Vec<Section> {
+    fn validate(&self, path: &str, problems: &mut Vec<String>) {
+        let listed: Vec<String> = self
+            .iter()
+            .flat_map(|section| section.questions.iter().cloned())
+            .collect();
+        for id in duplicates(&listed) {
+            problems.push(format!("{path} lists '{id}' more than once"));
+        }
+    }
+}
+
+impl Validate for LocationOverrides {
+    fn validate(&self, path: &str, problems: &mut Vec<String>) {
+        for (location, overrides) in self {
+            if !overrides.is_object() {
+                problems.push(format!("{path}.{location} has to be an object"));
+            }
+        }
+    }
+}
+
+impl Validate for DatabaseView {
+    fn validate(&self, path: &str, problems: &mut Vec<String>) {
+        for column in duplicates(&self.order) {
+            problems.push(format!("{path}.order lists '{column}' more than once"));
+        }
+        for column in self.order.iter() {
+            if !self.columns.contains_key(column) {
+                problems.push(format!("{path}.order lists unknown column '{column}'"));
+            }
+        }
+    }
+}
+
+impl QcChecks {
+    fn parse(raw: &Value, problems: &mut Vec<String>) -> Option<Self> {
+        let Some(raw_questions) = raw["questions"].as_object() else {
+            problems.push("qc_checks.questions has to be an object".into());
+            return None;
+        };
+        let mut questions = BTreeMap::new();
+        for (id, question) in raw_questions {
+            let path = format!("qc_checks.questions.{id}");
+            if let Some(question) = parse(question, &path, problems) {
+                questions.insert(id.clone(), question);
+            }
+        }
+        let passes = parse_optional(&raw["passes"], "qc_checks.passes", problems);
+        let tech_form = parse(&raw["tech_form"], "qc_checks.tech_form", problems);
+        let pdf_form = parse_optional(&raw["pdf_form"], "qc_checks.pdf_form", problems);
+
+        if questions.len() != raw_questions.len() {
+            return None;
+        }
+        Some(Self {
+            definition: raw.to_string(),
+            passes: passes?,
+            questions,
+            tech_form: tech_form?,
+            pdf_form: pdf_form?,
+        })
+    }
+
+    /// The checks that need the rest of the config
+    fn check(&self, model: &ConfigModel, config: &Config, problems: &mut Vec<String>) {
+        let forms = [
+            ("tech_form", Some(&self.tech_form)),
+            ("pdf_form", self.pdf_form.as_ref()),
+        ];
+        for (name, sections) in forms {
+            for id in sections
+                .into_iter()
+                .flatten()
+                .flat_map(|section| section.questions.iter())
+            {
+                if !self.questions.contains_key(id) {
+                    problems.push(format!("qc_checks.{name} lists unknown question '{id}'"));
+                }
+            }
+        }
+
+        // an empty form still parses the rule and checks its columns
+        let form = Value::Object(Default::default());
+        for (id, question) in &self.questions {
+            let path = format!("qc_checks.questions.{id}");
+            for build_type in question.whitelist_build_types.iter().flatten() {
+                if !model.build_types.values.contains_key(build_type) {
+                    problems.push(format!(
+                        "{path}.whitelist_build_types lists unknown build type '{build_type}'"
+                    ));
+                }
+            }
+            if let Err(err) = qc_checklist::applies(config, id, &form) {
+                problems.push(format!("{path}.applies_when: {err}"));
+            }
+        }
+    }
+}
+
+impl ConfigModel {
+    fn parse(raw: &Value, problems: &mut Vec<String>) -> Option<Self> {
+        if !raw.is_object() {
+            problems.push("the config has to be an object".into());
+            return None;
+        }
+        let qc_checks = QcChecks::parse(&raw["qc_checks"], problems);
+        let location_overrides =
+            parse_optional(&raw["location_overrides"], "location_overrides", problems)
+                .map(Option::unwrap_or_default);
+        Some(parse_fields!(
+            raw,
+            problems,
+            ConfigModel {
+                initials,
+                sales_order,
+                oem_serial,
+                make_model,
+                item_serial,
+                asm_serial,
+                build_locations,
+                build_types,
+                operating_systems,
+                processor_types,
+                ram_types,
+                ram_sizes,
+                processor_gens,
+                drive_types,
+                drive_sizes,
+                database,
+            },
+            qc_checks,
+            location_overrides
+        ))
+    }
+
+    /// The checks across parts of the config, only done once every part parsed
+    fn check(&self, config: &Config, problems: &mut Vec<String>) {
+        self.qc_checks.check(self, config, problems);
+
+        for location in self.location_overrides.keys() {
+            if !self.build_locations.values.contains_key(location) {
+                problems.push(format!(
+                    "location_overrides has unknown build location '{location}'"
+                ));
+                continue;
+            }
+            // every location has to end up with a valid config of its own
+            if let Err(ConfigProblems(more)) = config.for_location(location).model() {
+                problems.extend(
+                    more.into_iter()
+                        .map(|problem| format!("location_overrides.{location}: {problem}")),
+                );
+            }
+        }
+    }
+}
+
+fn duplicates(list: &[String]) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for entry in list {
+        if !seen.insert(entry.as_str()) && !duplicates.contains(&entry.as_str()) {
+            duplicates.push(entry.as_str());
+        }
+    }
+    duplicates
+}
+
+/// Only the keys of the config as written, `serde_json::Value` silently keeps the
+/// last one of duplicate keys
+enum KeyTree {
+    Map(Vec<(String, KeyTree)>),
+    Seq(Vec<KeyTree>),
+    Leaf,
+}
+
+impl KeyTree {
+    fn duplicate_keys(&self, path: &str, problems: &mut Vec<String>) {
+        let join = |key: &str| match path {
+            "" => key.to_owned(),
+            path => format!("{path}.{key}"),
+        };
+        match self {
+            KeyTree::Map(entries) => {
+                let keys: Vec<String> = entries.iter().map(|(key, _)| key.clone()).collect();
+                for key in duplicates(&keys) {
+                    problems.push(format!("{} is defined more than once", join(key)));
+                }
+                for (key, value) in entries {
+                    value.duplicate_keys(&join(key), problems);
+                }
+            }
+            KeyTree::Seq(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    item.duplicate_keys(&join(&index.to_string()), problems);
+                }
+            }
+            KeyTree::Leaf => {}
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyTreeVisitor;
+
+        impl<'de> Visitor<'de> for KeyTreeVisitor {
+            type Value = KeyTree;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("any json value")
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<KeyTree, E> {
+                Ok(KeyTree::Leaf)
+            }
+            fn visit_i64<E>(self, _: i64) -> Result<KeyTree, E> {
+                Ok(KeyTree::Leaf)
+            }
+            fn visit_u64<E>(self, _: u64) -> Result<KeyTree, E> {
+                Ok(KeyTree::Leaf)
+            }
+            fn visit_f64<E>(self, _: f64) -> Result<KeyTree, E> {
+                Ok(KeyTree::Leaf)
+            }
+            fn visit_str<E>(self, _: &str) -> Result<KeyTree, E> {
+                Ok(KeyTree::Leaf)
+            }
+            fn visit_unit<E>(self) -> Result<KeyTree, E> {
+                Ok(KeyTree::Leaf)
+            }
+            fn visit_none<E>(self) -> Result<KeyTree, E> {
+                Ok(KeyTree::Leaf)
+            }
+            fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<KeyTree, D::Error> {
+                KeyTree::deserialize(d)
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<KeyTree, A::Error> {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(KeyTree::Seq(items))
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<KeyTree, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(KeyTree::Map(entries))
+            }
+        }
+
+        deserializer.deserialize_any(KeyTreeVisitor)
+    }
+}
+
+/// The config in use, it is swapped as a whole when the file is reloaded so a
+/// request keeps the snapshot it started with. Clones share the same config
+#[derive(Debug, Clone)]
+pub struct LiveConfig {
+    path: PathBuf,
+    current: Arc<RwLock<Arc<Config>>>,
+}
+
+impl LiveConfig {
+    pub(crate) fn load(path: PathBuf) -> Result<Self, ConfigProblems> {
+        let config = Config::load(&path)?;
+        Ok(Self {
+            path,
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Reads and validates the config file without putting it into use
+    pub fn read(&self) -> Result<Config, ConfigProblems> {
+        Config::load(&self.path)
+    }
+
+    pub fn swap(&self, config: Arc<Config>) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = config;
+    }
+}
+
+/// Loads the file under the `config` key and manages it as the `LiveConfig`
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Config", |rocket| async {
+        let path = rocket
+            .figment()
+            .extract_inner::<RelativePathBuf>("config")
+            .map(|p| p.relative());
+
+        let path = match path {
+            Ok(dir) => dir,
+            Err(e) => {
+                rocket::config::pretty_print_error(e);
+                return Err(rocket);
+            }
+        };
+
+        if path.exists() && path.is_file() {
+            match LiveConfig::load(path.clone()) {
+                Ok(ok) => Ok(rocket.manage(ok)),
+                Err(problems) => {
+                    rocket::error!("Provided config '{}' is invalid", path.display());
+                    for problem in problems.0 {
+                        rocket::error_!("{problem}");
+                    }
+                    Err(rocket)
+                }
+            }
+        } else {
+            if path.exists() {
+                rocket::error!("Provided config path '{}' is not a file", path.display());
+            } else {
+                rocket::error!("Provided config path '{}' does not exist", path.display());
+            }
+            Err(rocket)
+        }
+    })
+}
+
+#[derive(Debug)]
+pub struct FailedToObtainConfig;
+
+/// The config a request started with, cached so every guard of the request sees the same one
+fn request_config<'r>(request: &'r rocket::Request<'_>) -> Option<&'r Arc<Config>> {
+    let live = request.rocket().state::<LiveConfig>()?;
+    Some(request.local_cache(|| live.current()))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Config {
+    type Error = FailedToObtainConfig;
+
+    async fn from_request(
+        request: &'r rocket::Request<'_>,
+    ) -> rocket::request::Outcome<Self, Self::Error> {
+        match request_config(request) {
+            Some(config) => rocket::request::Outcome::Success(config),
+            None => rocket::outcome::Outcome::Error((
+                rocket::http::Status::InternalServerError,
+                FailedToObtainConfig,
+            )),
+        }
+    }
+}
+
+/// The config of a request as a handle for handlers that move it into `Db::run`, cloning
+/// it shares the config instead of copying it
+#[derive(Debug, Clone)]
+pub struct SharedConfig(pub Arc<Config>);
+
+impl Deref for SharedConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SharedConfig {
+    type Error = FailedToObtainConfig;
+
+    async fn from_request(
+        request: &'r rocket::Request<'_>,
+    ) -> rocket::request::Outcome<Self, Self::Error> {
+        match request_config(request) {
+            Some(config) => rocket::request::Outcome::Success(SharedConfig(config.clone())),
+            None => rocket::outcome::Outcome::Error((
+                rocket::http::Status::InternalServerError,
+                FailedToObtainConfig,
+            )),
+        }
+    }
+}
+
+#[test]
+fn test_validate() {
+    let config = Config::load("./config.json5").expect("shipped config should be valid");
+    let model = config.model().expect("shipped config should be valid");
+    assert_eq!(
+        model.drive_sizes.order.len(),
+        model.drive_sizes.values.len()
+    );
+
+    let mut broken = Config::new(config.0.clone());
+    broken.0["drive_sizes"]["order"][1] = "GB128".into();
+    broken.0["ram_types"]["order"][0] = "DDR1".into();
+    broken.0["initials"]["pattern"] = "[A-Z{2}".into();
+    broken.0["qc_checks"]["questions"]["bios_date"]["applies_when"] = "not_a_column = 1".into();
+    broken.0["qc_checks"]["questions"]["check_battery"]["whitelist_build_types"][0] =
+        "phone".into();
+    broken.0["qc_checks"]["tech_form"][0]["questions"][0] = "no_such_question".into();
+    let problems = broken
+        .model()
+        .expect_err("broken config should be rejected")
+        .0;
+    assert_eq!(problems.len(), 6, "{problems:#?}");
+
+    // a question that fails to parse is reported along with the other problems
+    let mut items = broken.0;
+    items["qc_checks"]["questions"]["bios_version"]["type"] = "txt".into();
+    items["build_types"]["order"] = "desktop".into();
+    let problems = Config::new(items)
+        .model()
+        .expect_err("broken config should be rejected")
+        .0;
+    assert!(problems
+        .iter()
+        .any(|p| p.starts_with("qc_checks.questions.bios_version:")));
+    assert!(problems.iter().any(|p| p.starts_with("build_types:")));
+    assert!(problems.iter().any(|p| p.starts_with("initials.pattern")));
+
+    let mut problems = Vec::new();
+    json5::from_str::<KeyTree>(r#"{"a": {"b": 1, "c": [{"d": 1, "d": 2}], "b": 2}}"#)
+        .unwrap()
+        .duplicate_keys("", &mut problems);
+    assert_eq!(
+        problems,
+        [
+            "a.b is defined more than once",
+            "a.c.0.d is defined more than once"
+        ]
+    );
+}
+
+#[test]
+fn test_location_overrides() {
+    let mut config = Config::load_from_file("./config.json5").expect("Failed to load config file");
+    config.0["location_overrides"] = serde_json::json!({
+        "GTA": {
+            "asm_serial": {"pattern": "GTA-[0-9]{5}"},
+            "qc_checks": {
+                "questions": {"cmos_battery": null, "bios_date": {"question": "BIOS date set"}},
+                "tech_form": [{"heading": "Checks", "questions": ["bios_date"]}]
+            }
+        }
+    });
+    config.model().expect("overrides should be valid");
+
+    let gta = config.for_location("GTA");
+    assert_eq!(gta.0["asm_serial"]["pattern"], "GTA-[0-9]{5}");
+    assert_eq!(
+        gta.0["asm_serial"]["title"],
+        config.0["asm_serial"]["title"]
+    );
+    assert_eq!(
+        gta.0["qc_checks"]["questions"]["bios_date"]["question"],
+        "BIOS date set"
+    );
+    assert!(gta.0["qc_checks"]["questions"]
+        .get("cmos_battery")
+        .is_none());
+    assert!(gta.0.get("location_overrides").is_none());
+
+    let nia = config.for_location("NIA");
+    assert_eq!(nia.0["asm_serial"], config.0["asm_serial"]);
+    assert_eq!(nia.0["qc_checks"], config.0["qc_checks"]);
+
+    // overrides are checked as part of the location's own config
+    let mut items = config.0;
+    items["location_overrides"]["GTA"]["qc_checks"]["tech_form"][0]["questions"][0] =
+        "cmos_battery".into();
+    items["location_overrides"]["XYZ"] = serde_json::json!({});
+    let problems = Config::new(items).model().expect_err("broken overrides").0;
+    assert_eq!(
+        problems,
+        [
+            "location_overrides.GTA: qc_checks.tech_form lists unknown question 'cmos_battery'",
+            "location_overrides has unknown build location 'XYZ'",
+        ]
+    );
+}
@@ -1,6 +1,5 @@
-use rocket::Shutdown;
 use rocket::{
-    fairing::AdHoc,
+    fairing::{self, AdHoc},
     futures::{SinkExt, StreamExt},
     http::Status,
     request::FromRequest,
@@ -8,18 +7,69 @@ use rocket::{
         select,
         sync::{Mutex, Notify, RwLock},
     },
+    Build, Rocket, Shutdown,
+};
+use rocket_sync_db_pools::{
+    diesel::{QueryResult, SqliteConnection},
+    ConnectionPool,
 };
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, VecDeque},
     sync::{
-        atomic::{AtomicBool, AtomicUsize},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::Relaxed},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
-#[derive(Serialize, Deserialize)]
+use crate::database::Db;
+use crate::Config;
+
+use self::payload::Item;
+use self::protocol::{QueueEvent, Queued};
+
+pub mod admin;
+pub mod auth;
+pub mod broadcast;
+pub mod http;
+pub mod payload;
+pub mod protocol;
+mod store;
+
+/// Read from the `copy_session_*` keys of the rocket config, all in seconds
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    // how long the queue of a disconnected producer is kept for it to re-attach
+    pub grace_period: Duration,
+    // unused sessions without any activity for this long are removed
+    pub idle_timeout: Duration,
+    // how often version 2 clients are pinged, two missed beats closes the connection
+    pub heartbeat: Duration,
+    // how long a version 2 consumer has to ack an item before it is queued again
+    pub ack_timeout: Duration,
+}
+
+impl Timeouts {
+    fn from_figment(figment: &rocket::figment::Figment) -> Self {
+        let seconds = |key: &str, default: u64| {
+            Duration::from_secs(
+                figment
+                    .extract_inner::<u64>(&format!("copy_session_{key}"))
+                    .unwrap_or(default),
+            )
+        };
+        Self {
+            grace_period: seconds("grace_period", 300),
+            idle_timeout: seconds("idle_timeout", 12 * 60 * 60),
+            heartbeat: seconds("heartbeat", 15),
+            ack_timeout: seconds("ack_timeout", 30),
+        }
+    }
+}
 
+#[derive(Serialize, Deserialize)]
+#[allow(dead_code)]
 enum Message {
     SessionNotOpen,
     SessionEnded,
@@ -27,17 +77,176 @@ enum Message {
     Data(String),
 }
 
-#[derive(Default)]
+#[derive(Clone)]
 pub struct Sessions {
-    sessions: RwLock<HashMap<String, Arc<SessionInfo>>>,
-    session_notify: Notify,
+    sessions: Arc<RwLock<HashMap<String, Arc<SessionInfo>>>>,
+    session_notify: Arc<Notify>,
+    // queued items are mirrored here so they outlive the server and dropped producers
+    store: ConnectionPool<Db, SqliteConnection>,
+    timeouts: Timeouts,
 }
 
 impl Sessions {
-    pub fn new() -> Self {
+    pub fn new(store: ConnectionPool<Db, SqliteConnection>, timeouts: Timeouts) -> Self {
         Self {
             sessions: Default::default(),
-            session_notify: Notify::new(),
+            session_notify: Arc::new(Notify::new()),
+            store,
+            timeouts,
+        }
+    }
+
+    /// Runs `f` against the session store. The in memory queue keeps working
+    /// without it so failures are only logged
+    async fn store<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut SqliteConnection) -> QueryResult<R> + Send + 'static,
+    ) -> Option<R> {
+        let Some(conn) = self.store.get().await else {
+            rocket::error!("Failed to get a connection to the session store");
+            return None;
+        };
+        match conn.run(f).await {
+            Ok(ok) => Some(ok),
+            Err(err) => {
+                rocket::error!("Session store error: {err}");
+                None
+            }
+        }
+    }
+
+    /// Queues the item and returns its id
+    async fn push_back(&self, session_id: &str, session: &SessionInfo, val: Item) -> u64 {
+        let id = session_id.to_owned();
+        let stored = val.to_stored();
+        let row = self
+            .store(move |conn| store::push(conn, &id, &stored))
+            .await;
+        session.broadcast.publish(val.clone()).await;
+
+        let queued = Queued {
+            id: session.next_item_id.fetch_add(1, Relaxed),
+            row,
+            item: val,
+        };
+        let item_id = queued.id;
+        let mut data = session.data.lock().await;
+        session.emit(QueueEvent::Added {
+            index: data.len(),
+            queued: queued.clone(),
+        });
+        data.push_back(queued);
+        drop(data);
+
+        session.touch();
+        session.notify_consumer.notify_one();
+        session.notify_change.notify_waiters();
+        item_id
+    }
+
+    async fn pop_front(&self, session_id: &str, session: &SessionInfo) -> Option<Item> {
+        let queued = self.take_front(session).await?;
+        Some(self.ack(session_id, session, queued).await)
+    }
+
+    /// Takes the first item off the queue, it stays stored until it is acked
+    async fn take_front(&self, session: &SessionInfo) -> Option<Queued> {
+        let mut data = session.data.lock().await;
+        let queued = data.pop_front()?;
+        session.emit(QueueEvent::Removed(queued.id));
+        drop(data);
+        session.touch();
+        session.notify_change.notify_waiters();
+        Some(queued)
+    }
+
+    async fn ack(&self, session_id: &str, session: &SessionInfo, queued: Queued) -> Item {
+        if let Some(row) = queued.row {
+            let id = session_id.to_owned();
+            self.store(move |conn| store::remove(conn, &id, row)).await;
+        }
+        session.touch();
+        queued.item
+    }
+
+    /// Puts an item that wasn't acked back at the front of the queue
+    async fn requeue(&self, session: &SessionInfo, queued: Queued) {
+        let mut data = session.data.lock().await;
+        session.emit(QueueEvent::Added {
+            index: 0,
+            queued: queued.clone(),
+        });
+        data.push_front(queued);
+        drop(data);
+
+        session.notify_consumer.notify_one();
+        session.notify_change.notify_waiters();
+        session.queue_updated.notify_waiters();
+    }
+
+    /// Starts the grace period of a producer that went away
+    async fn detach_producer(&self, session_id: &str, session: &Arc<SessionInfo>) {
+        self.session_notify.notify_waiters();
+
+        let id = session_id.to_owned();
+        self.store(move |conn| store::detach(conn, &id)).await;
+        self.discard_after(
+            session_id.to_owned(),
+            session.clone(),
+            self.timeouts.grace_period,
+        );
+        session.notify_change.notify_waiters();
+        session.notify_session_over.notify_waiters();
+    }
+
+    /// Throws the queue away once `delay` has passed, unless its producer re-attached by then
+    fn discard_after(&self, session_id: String, session: Arc<SessionInfo>, delay: Duration) {
+        let sessions = self.clone();
+        let attachments = session.attachments.load(Relaxed);
+        rocket::tokio::spawn(async move {
+            rocket::tokio::time::sleep(delay).await;
+
+            // held so the producer can't re-attach half way through
+            let mut producer = session.producer.lock().await;
+            if session.live.load(Relaxed) || session.attachments.load(Relaxed) != attachments {
+                return;
+            }
+            let id = session_id.clone();
+            sessions.store(move |conn| store::discard(conn, &id)).await;
+            *producer = None;
+            drop(producer);
+
+            let mut data = session.data.lock().await;
+            data.clear();
+            session.emit(QueueEvent::Cleared);
+            drop(data);
+            session.notify_change.notify_waiters();
+        });
+    }
+
+    /// Removes the sessions nobody is connected to that have been idle for too long, along
+    /// with the broadcast subscribers that went away more than a grace period ago
+    async fn expire_idle(&self) {
+        let mut lock = self.sessions.write().await;
+        let expired: Vec<String> = lock
+            .iter()
+            .filter(|(_, session)| {
+                Arc::strong_count(session) == 1 && session.idle() >= self.timeouts.idle_timeout
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            lock.remove(id);
+        }
+        let remaining: Vec<Arc<SessionInfo>> = lock.values().cloned().collect();
+        // the store is slow next to everything else waiting on the lock
+        drop(lock);
+
+        for id in expired {
+            self.store(move |conn| store::delete(conn, &id)).await;
+        }
+        for session in remaining {
+            session.broadcast.expire(self.timeouts.grace_period).await;
         }
     }
 }
@@ -47,16 +256,25 @@ pub struct SessionInfo {
     notify_change: Notify,
     notify_session_over: Notify,
     live: AtomicBool,
-    data: Mutex<VecDeque<String>>,
+    // whoever last produced into the session, a queue they left behind is kept for them
+    producer: Mutex<Option<String>>,
+    // bumped every time a producer attaches
+    attachments: AtomicUsize,
+    data: Mutex<VecDeque<Queued>>,
+    next_item_id: AtomicU64,
+    // queue changes for version 2 producers
+    events: rocket::tokio::sync::broadcast::Sender<QueueEvent>,
+    // (producer, msg_id, item id) of the latest version 2 pushes
+    recent_pushes: std::sync::Mutex<VecDeque<(String, u64, u64)>>,
     queue_info: RwLock<Vec<usize>>,
     next_queue_id: AtomicUsize,
     queue_updated: Notify,
-}
-
-impl Default for SessionInfo {
-    fn default() -> Self {
-        Self::new()
-    }
+    // what subscribers get, independent of the queue single consumers take from
+    broadcast: broadcast::Broadcast,
+    // issued when the session was created, every connection has to present it
+    token: String,
+    build_location: Option<String>,
+    last_active: std::sync::Mutex<Instant>,
 }
 
 impl Drop for SessionInfo {
@@ -66,19 +284,66 @@ impl Drop for SessionInfo {
 }
 
 impl SessionInfo {
-    pub fn new() -> Self {
+    pub fn new(token: String, build_location: Option<String>) -> Self {
         println!("new session");
         Self {
             notify_consumer: Notify::new(),
             notify_change: Notify::new(),
             notify_session_over: Notify::new(),
             data: Mutex::default(),
+            next_item_id: AtomicU64::new(0),
+            events: rocket::tokio::sync::broadcast::channel(64).0,
+            recent_pushes: Default::default(),
             next_queue_id: AtomicUsize::new(0),
             queue_info: Default::default(),
             live: AtomicBool::new(false),
+            producer: Mutex::default(),
+            attachments: AtomicUsize::new(0),
             queue_updated: Notify::new(),
+            broadcast: Default::default(),
+            token,
+            build_location,
+            last_active: std::sync::Mutex::new(Instant::now()),
         }
     }
+
+    fn restored(stored: store::StoredSession, data: Vec<(i32, String)>, idle: Duration) -> Self {
+        let mut session = Self::new(stored.token, stored.build_location);
+        *session.producer.get_mut() = stored.producer;
+        *session.data.get_mut() = data
+            .into_iter()
+            .zip(0..)
+            .map(|((row, data), id)| Queued {
+                id,
+                row: Some(row),
+                item: Item::from_stored(data),
+            })
+            .collect();
+        *session.next_item_id.get_mut() = session.data.get_mut().len() as u64;
+        if let Some(last_active) = Instant::now().checked_sub(idle) {
+            *session.last_active.get_mut().unwrap() = last_active;
+        }
+        session
+    }
+
+    /// The queue as version 1 producers get it after every change
+    async fn dump(&self) -> String {
+        let data = self.data.lock().await;
+        serde_json::to_string(&data.iter().map(|q| &q.item).collect::<Vec<_>>()).unwrap_or_default()
+    }
+
+    fn touch(&self) {
+        if let Ok(mut last_active) = self.last_active.lock() {
+            *last_active = Instant::now();
+        }
+    }
+
+    fn idle(&self) -> Duration {
+        self.last_active
+            .lock()
+            .map(|last_active| last_active.elapsed())
+            .unwrap_or_default()
+    }
 }
 
 #[rocket::async_trait]
@@ -96,55 +361,105 @@ impl<'r> FromRequest<'r> for &'r Sessions {
     }
 }
 
-#[get("/open_session/<session_id>")]
-pub async fn open_new_session(
+#[derive(Serialize)]
+enum ProducerMessage {
+    Rejected(payload::PayloadError),
+}
+
+fn refuse<'r>(websocket: ws::WebSocket, reason: &'static str) -> ws::Channel<'r> {
+    websocket.channel(move |mut stream| {
+        Box::pin(async move {
+            stream
+                .close(Some(ws::frame::CloseFrame {
+                    code: ws::frame::CloseCode::Error,
+                    reason: reason.into(),
+                }))
+                .await
+        })
+    })
+}
+
+#[get("/open_session/<session_id>?<producer>&<token>&<build_location>&<version>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn open_new_session<'r>(
     websocket: ws::WebSocket,
-    sessions: &Sessions,
+    sessions: &'r Sessions,
+    config: &'r Config,
     session_id: String,
+    producer: Option<String>,
+    token: Option<&str>,
+    build_location: Option<&str>,
+    version: Option<u8>,
     shutdown: Shutdown,
-) -> ws::Channel<'_> {
-    // let mut map = HashMap::new();
-    // for (key, val) in &*sessions.sessions.read().await{
-    //     let val = Vec::from(val.data.lock().await.clone());
-    //     map.insert(key.clone(), val);
-    // }
-    // rocket::
-    let mut lock = sessions.sessions.write().await;
-    let session = lock.entry(session_id.clone()).or_default().clone();
-    if session.live.swap(true, Relaxed) {
-        return websocket.channel(move |mut stream| {
-            Box::pin(async move {
-                stream
-                    .close(Some(ws::frame::CloseFrame {
-                        code: ws::frame::CloseCode::Error,
-                        reason: "SessionId already In use".into(),
-                    }))
-                    .await
-            })
-        });
+) -> ws::Channel<'r> {
+    let version = version.unwrap_or(1);
+    if !(1..=protocol::VERSION).contains(&version) {
+        return refuse(websocket, "Unsupported protocol version");
     }
-    drop(lock);
+    let session = match sessions.authorize(&session_id, token, build_location).await {
+        Ok(session) => session,
+        Err(reason) => return refuse(websocket, reason),
+    };
+    let attached = if session.live.swap(true, Relaxed) {
+        Err("SessionId already In use")
+    } else {
+        let mut previous = session.producer.lock().await;
+        // a queue left by a named producer is kept for it until the grace period ends,
+        // neither another name nor an anonymous producer can take it over
+        if previous.is_some()
+            && *previous != producer
+            && !session.data.lock().await.is_empty()
+        {
+            session.live.store(false, Relaxed);
+            Err("SessionId reserved for its previous producer")
+        } else {
+            previous.clone_from(&producer);
+            Ok(session.attachments.fetch_add(1, Relaxed) + 1)
+        }
+    };
+    let attachment = match attached {
+        Ok(attachment) => attachment,
+        Err(reason) => return refuse(websocket, reason),
+    };
+
+    // anonymous producers can only retry pushes on the same connection
+    let pusher = producer
+        .clone()
+        .unwrap_or_else(|| format!("attachment-{attachment}"));
+    let id = session_id.clone();
+    sessions
+        .store(move |conn| store::attach(conn, &id, producer.as_deref()))
+        .await;
 
     sessions.session_notify.notify_waiters();
     session.notify_session_over.notify_waiters();
-    use std::sync::atomic::Ordering::Relaxed;
     websocket.channel(move |mut stream| {
         Box::pin(async move {
             let val = async {
-                while session.live.load(std::sync::atomic::Ordering::Relaxed){
+                if version == 2 {
+                    protocol::produce(&mut stream, sessions, config, &session_id, &session, attachment, &pusher, shutdown.clone()).await?;
+                }
+                // a kicked producer stays out even if another one attached in the meantime
+                while version == 1 && session.live.load(Relaxed) && session.attachments.load(Relaxed) == attachment {
                     let shutdown = shutdown.clone();
                     select! {
                         Some(next_recv) = stream.next() => {
                             let next_recv = next_recv?;
                             match next_recv{
                                 ws::Message::Text(val) => {
-                                    session.data.lock().await.push_back(val);
-                                    session.notify_consumer.notify_one();
-                                    session.notify_change.notify_waiters();
+                                    let val = match Item::parse(config, val) {
+                                        Ok(val) => val,
+                                        Err(err) => {
+                                            let err = serde_json::to_string(&ProducerMessage::Rejected(err)).unwrap_or_default();
+                                            stream.send(ws::Message::Text(err)).await?;
+                                            continue;
+                                        }
+                                    };
+                                    sessions.push_back(&session_id, &session, val).await;
 
                                     rocket::tokio::task::yield_now().await;
 
-                                    let data = serde_json::to_string(&*session.data.lock().await).unwrap_or(String::new());
+                                    let data = session.dump().await;
                                     stream.send(ws::Message::Text(data)).await?;
                                 },
                                 ws::Message::Close(_) => break,
@@ -152,8 +467,7 @@ pub async fn open_new_session(
                             }
                         }
                         _ = session.notify_change.notified() => {
-                            let data = session.data.lock().await;
-                            let data = serde_json::to_string(&*data).unwrap_or(String::new());
+                            let data = session.dump().await;
                             stream.send(ws::Message::Text(data)).await?;
                         }
                         _ = shutdown => {
@@ -162,54 +476,62 @@ pub async fn open_new_session(
                     }
                 }
 
+                if !session.live.load(Relaxed) || session.attachments.load(Relaxed) != attachment {
+                    stream.close(Some(ws::frame::CloseFrame {
+                        code: ws::frame::CloseCode::Policy,
+                        reason: "Producer was kicked from the session".into(),
+                    })).await?;
+                }
                 Ok(())
             }.await;
 
-            sessions.session_notify.notify_waiters();
-
-            session.live.store(false, Relaxed);
-            session.data.lock().await.clear();
-            session.notify_change.notify_waiters();
-            session.notify_session_over.notify_waiters();
-            drop(session);
-
-            let mut lock = sessions.sessions.write().await;
-            if let Some(entry) = lock.get(&session_id){
-                if Arc::strong_count(entry) == 1{
-                    lock.remove(&session_id);
-                }
+            // otherwise the producer was kicked and already detached
+            if session.attachments.load(Relaxed) == attachment && session.live.swap(false, Relaxed) {
+                sessions.detach_producer(&session_id, &session).await;
             }
-            drop(lock);
+            drop(session);
 
             val
         })
     })
 }
 
-#[get("/consume_single/<session_id>")]
-pub async fn session_single_consumer(
+#[get("/consume_single/<session_id>?<token>&<build_location>&<version>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn session_single_consumer<'r>(
     websocket: ws::WebSocket,
-    sessions: &Sessions,
+    sessions: &'r Sessions,
     session_id: String,
+    token: Option<&str>,
+    build_location: Option<&str>,
+    version: Option<u8>,
     shutdown: Shutdown,
-) -> Result<ws::Channel<'_>, &str> {
+) -> Result<ws::Channel<'r>, &'r str> {
     #[derive(Deserialize, Serialize)]
     enum Message {
         SessionQueuePos(usize),
         NoSessionQueuePos(usize),
-        Consumed(String),
+        Consumed(Item),
+    }
+    let session = match sessions.authorize(&session_id, token, build_location).await {
+        Ok(session) => session,
+        Err(reason) => return Ok(refuse(websocket, reason)),
+    };
+    match version.unwrap_or(1) {
+        1 => {}
+        2 => {
+            return Ok(websocket.channel(move |mut stream| {
+                Box::pin(async move {
+                    protocol::consume(&mut stream, sessions, &session_id, &session, shutdown).await
+                })
+            }))
+        }
+        _ => return Ok(refuse(websocket, "Unsupported protocol version")),
     }
-    use std::sync::atomic::Ordering::Relaxed;
-    let session = sessions
-        .sessions
-        .write()
-        .await
-        .entry(session_id.clone())
-        .or_default()
-        .clone();
 
     Ok(websocket.channel(move |mut stream| {
         Box::pin(async move {
+            let session_key = &session_id;
             let ret = async move{
 
 
@@ -217,11 +539,11 @@ pub async fn session_single_consumer(
                 
                 let mut lock = session.queue_info.write().await;
                 if lock.is_empty(){
-                    let val = session.data.lock().await.pop_front();
+                    let val = sessions.pop_front(session_key, &session).await;
                     session.notify_change.notify_waiters();
                     if let Some(val) = val{
                         drop(lock);
-                        let val = serde_json::to_string(&Message::Consumed(val)).unwrap_or(String::new());
+                        let val = serde_json::to_string(&Message::Consumed(val)).unwrap_or_default();
                         stream.send(ws::Message::Text(val)).await?;
                         return Ok(())
                     }
@@ -241,10 +563,10 @@ pub async fn session_single_consumer(
                 }
 
                 if session.live.load(Relaxed){
-                    let val = serde_json::to_string(&Message::SessionQueuePos(queue_pos!())).unwrap_or(String::new());
+                    let val = serde_json::to_string(&Message::SessionQueuePos(queue_pos!())).unwrap_or_default();
                     stream.send(ws::Message::Text(val)).await?;
                 }else{
-                    let val = serde_json::to_string(&Message::NoSessionQueuePos(queue_pos!())).unwrap_or(String::new());
+                    let val = serde_json::to_string(&Message::NoSessionQueuePos(queue_pos!())).unwrap_or_default();
                     stream.send(ws::Message::Text(val)).await?;
                 }
 
@@ -257,36 +579,36 @@ pub async fn session_single_consumer(
                     select!{
                         _ = sessions.session_notify.notified() => {
                             if !session.live.load(Relaxed){
-                                let val = serde_json::to_string(&Message::NoSessionQueuePos(queue_pos!())).unwrap_or(String::new());
+                                let val = serde_json::to_string(&Message::NoSessionQueuePos(queue_pos!())).unwrap_or_default();
                                 stream.send(ws::Message::Text(val)).await?;
                             }else{
                                 let pos = queue_pos!();
                                 if pos == 0 {
-                                    let val = session.data.lock().await.pop_front();
+                                    let val = sessions.pop_front(session_key, &session).await;
                                     session.queue_updated.notify_waiters();
                                     if let Some(val) = val{
                                         session.notify_change.notify_waiters();
-                                        let val = serde_json::to_string(&Message::Consumed(val)).unwrap_or(String::new());
+                                        let val = serde_json::to_string(&Message::Consumed(val)).unwrap_or_default();
                                         stream.send(ws::Message::Text(val)).await?;
                                         return Ok(())
                                     }
 
-                                    let val = serde_json::to_string(&Message::SessionQueuePos(pos)).unwrap_or(String::new());
+                                    let val = serde_json::to_string(&Message::SessionQueuePos(pos)).unwrap_or_default();
                                     stream.send(ws::Message::Text(val)).await?;
                                 }else{
-                                    let val = serde_json::to_string(&Message::SessionQueuePos(pos)).unwrap_or(String::new());
+                                    let val = serde_json::to_string(&Message::SessionQueuePos(pos)).unwrap_or_default();
                                     stream.send(ws::Message::Text(val)).await?;
                                 }
                             }
                         }
                         _ = session.queue_updated.notified() => {
                             if !session.live.load(Relaxed){
-                                let val = serde_json::to_string(&Message::NoSessionQueuePos(queue_pos!())).unwrap_or(String::new());
+                                let val = serde_json::to_string(&Message::NoSessionQueuePos(queue_pos!())).unwrap_or_default();
                                 stream.send(ws::Message::Text(val)).await?;
                             }else{
                                 let pos = queue_pos!();
                                 if pos == 0 {
-                                    let val = session.data.lock().await.pop_front();
+                                    let val = sessions.pop_front(session_key, &session).await;
                                     // session.waiting.fetch_sub(1, Relaxed);
 
                                     if let Some(val) = val{
@@ -298,25 +620,25 @@ pub async fn session_single_consumer(
                                         session.queue_updated.notify_waiters();
 
                                         session.notify_change.notify_waiters();
-                                        let val = serde_json::to_string(&Message::Consumed(val)).unwrap_or(String::new());
+                                        let val = serde_json::to_string(&Message::Consumed(val)).unwrap_or_default();
                                         stream.send(ws::Message::Text(val)).await?;
                                         return Ok(())
                                     }
 
-                                    let val = serde_json::to_string(&Message::SessionQueuePos(pos)).unwrap_or(String::new());
+                                    let val = serde_json::to_string(&Message::SessionQueuePos(pos)).unwrap_or_default();
                                     stream.send(ws::Message::Text(val)).await?;
                                 }else{
-                                    let val = serde_json::to_string(&Message::SessionQueuePos(pos)).unwrap_or(String::new());
+                                    let val = serde_json::to_string(&Message::SessionQueuePos(pos)).unwrap_or_default();
                                     stream.send(ws::Message::Text(val)).await?;
                                 }
                             }
                         }
                         _ = &mut line_future => {
                             if !session.live.load(Relaxed){
-                                let val = serde_json::to_string(&Message::NoSessionQueuePos(queue_pos!())).unwrap_or(String::new());
+                                let val = serde_json::to_string(&Message::NoSessionQueuePos(queue_pos!())).unwrap_or_default();
                                 stream.send(ws::Message::Text(val)).await?;
                             }else{
-                                let val = session.data.lock().await.pop_front();
+                                let val = sessions.pop_front(session_key, &session).await;
                                 // session.waiting.fetch_sub(1, Relaxed);
 
                                 if let Some(val) = val{
@@ -330,7 +652,7 @@ pub async fn session_single_consumer(
                                     }
                                     session.queue_updated.notify_waiters();
                                     session.notify_change.notify_waiters();
-                                    let val = serde_json::to_string(&Message::Consumed(val)).unwrap_or(String::new());
+                                    let val = serde_json::to_string(&Message::Consumed(val)).unwrap_or_default();
                                     stream.send(ws::Message::Text(val)).await?;
                                     return Ok(())
                                 }
@@ -359,14 +681,6 @@ pub async fn session_single_consumer(
             ret
             }.await;
 
-            let mut lock = sessions.sessions.write().await;
-            if let Some(entry) = lock.get(&session_id){
-                if Arc::strong_count(entry) == 1{
-                    lock.remove(&session_id);
-                }
-            }
-            drop(lock);
-
             ret
         })
     }))
@@ -375,7 +689,80 @@ pub async fn session_single_consumer(
 pub fn stage() -> AdHoc {
     AdHoc::on_ignite("Sessions", |rocket| async {
         rocket
-            .manage(Sessions::new())
-            .mount("/api", routes![open_new_session, session_single_consumer])
+            .attach(AdHoc::try_on_ignite("Restore Sessions", restore_sessions))
+            .mount("/api", routes![
+                    open_new_session,
+                    session_single_consumer,
+                    broadcast::session_subscriber,
+                    admin::list_sessions,
+                    admin::delete_session_item,
+                    admin::clear_session,
+                    admin::kick_producer,
+                    admin::delete_session,
+                    auth::new_session,
+                    http::push,
+                    http::pop
+                ])
+            .attach(AdHoc::on_liftoff("Expire Sessions", |rocket| {
+                Box::pin(async move {
+                    let (Some(sessions), shutdown) =
+                        (rocket.state::<Sessions>().cloned(), rocket.shutdown())
+                    else {
+                        return;
+                    };
+
+                    rocket::tokio::spawn(async move {
+                        let mut interval =
+                            rocket::tokio::time::interval(Duration::from_secs(60));
+                        loop {
+                            let shutdown = shutdown.clone();
+                            select! {
+                                _ = shutdown => return,
+                                _ = interval.tick() => sessions.expire_idle().await,
+                            }
+                        }
+                    });
+                })
+            }))
     })
 }
+
+/// Loads the sessions left over from the last run, their producers get
+/// whatever remains of the grace period to re-attach
+async fn restore_sessions(rocket: Rocket<Build>) -> fairing::Result {
+    let timeouts = Timeouts::from_figment(rocket.figment());
+
+    let Some(store) = Db::pool(&rocket).cloned() else {
+        rocket::error!("Copy sessions need the diesel database");
+        return Err(rocket);
+    };
+    let sessions = Sessions::new(store, timeouts);
+
+    let restored = sessions.store(store::load_all).await.unwrap_or_default();
+    let now = time::OffsetDateTime::now_utc();
+    for (stored, items) in restored {
+        let elapsed = match stored.disconnected {
+            Some(disconnected) => Duration::try_from(now - disconnected.0).unwrap_or_default(),
+            None => {
+                // the producer was still connected when the server went down
+                let id = stored.session_id.clone();
+                sessions.store(move |conn| store::detach(conn, &id)).await;
+                Duration::ZERO
+            }
+        };
+        let idle = stored
+            .last_active
+            .and_then(|last_active| Duration::try_from(now - last_active.0).ok())
+            .unwrap_or_default();
+        let session_id = stored.session_id.clone();
+        let session = Arc::new(SessionInfo::restored(stored, items, idle));
+        sessions
+            .sessions
+            .write()
+            .await
+            .insert(session_id.clone(), session.clone());
+        sessions.discard_after(session_id, session, timeouts.grace_period.saturating_sub(elapsed));
+    }
+
+    Ok(rocket.manage(sessions))
+}
@@ -0,0 +1,140 @@
+use crate::admin_pwd::Admin;
+
+use rocket::serde::json::Json;
+use serde::Serialize;
+use std::sync::{atomic::Ordering::Relaxed, Arc};
+
+use super::{protocol::QueueEvent, store, SessionInfo, Sessions};
+
+#[derive(Debug, Serialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    // whether a producer is connected
+    pub live: bool,
+    pub producer: Option<String>,
+    pub build_location: Option<String>,
+    pub queue_length: usize,
+    // single consumers waiting in `queue_info`
+    pub waiting_consumers: usize,
+    pub subscribers: usize,
+}
+
+impl SessionSummary {
+    async fn new(session_id: String, session: &SessionInfo) -> Self {
+        Self {
+            session_id,
+            live: session.live.load(Relaxed),
+            producer: session.producer.lock().await.clone(),
+            build_location: session.build_location.clone(),
+            queue_length: session.data.lock().await.len(),
+            waiting_consumers: session.queue_info.read().await.len(),
+            subscribers: session.broadcast.subscribers().await,
+        }
+    }
+}
+
+impl Sessions {
+    async fn get(&self, session_id: &str) -> Option<Arc<SessionInfo>> {
+        self.sessions.read().await.get(session_id).cloned()
+    }
+}
+
+#[get("/sessions")]
+pub(super) async fn list_sessions(sessions: &Sessions) -> Json<Vec<SessionSummary>> {
+    let mut all: Vec<_> = sessions
+        .sessions
+        .read()
+        .await
+        .iter()
+        .map(|(id, session)| (id.clone(), session.clone()))
+        .collect();
+    all.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut summaries = Vec::with_capacity(all.len());
+    for (id, session) in all {
+        summaries.push(SessionSummary::new(id, &session).await);
+    }
+    Json(summaries)
+}
+
+#[delete("/delete_session_item/<session_id>/<index>")]
+pub(super) async fn delete_session_item(
+    sessions: &Sessions,
+    session_id: String,
+    index: usize,
+    _admin: Admin,
+) -> Option<Json<SessionSummary>> {
+    let session = sessions.get(&session_id).await?;
+    let mut data = session.data.lock().await;
+    let queued = data.remove(index)?;
+    session.emit(QueueEvent::Removed(queued.id));
+    drop(data);
+
+    if let Some(row) = queued.row {
+        let id = session_id.clone();
+        sessions
+            .store(move |conn| store::remove(conn, &id, row))
+            .await;
+    }
+    session.notify_change.notify_waiters();
+
+    Some(Json(SessionSummary::new(session_id, &session).await))
+}
+
+#[post("/clear_session/<session_id>")]
+pub(super) async fn clear_session(
+    sessions: &Sessions,
+    session_id: String,
+    _admin: Admin,
+) -> Option<Json<SessionSummary>> {
+    let session = sessions.get(&session_id).await?;
+    let mut data = session.data.lock().await;
+    data.clear();
+    session.emit(QueueEvent::Cleared);
+    drop(data);
+
+    let id = session_id.clone();
+    sessions.store(move |conn| store::clear(conn, &id)).await;
+    session.notify_change.notify_waiters();
+
+    Some(Json(SessionSummary::new(session_id, &session).await))
+}
+
+/// Frees the id of a producer that is stuck as live, its queue is kept for the grace period
+#[post("/kick_producer/<session_id>")]
+pub(super) async fn kick_producer(
+    sessions: &Sessions,
+    session_id: String,
+    _admin: Admin,
+) -> Option<Json<SessionSummary>> {
+    let session = sessions.get(&session_id).await?;
+    if session.live.swap(false, Relaxed) {
+        // lets someone else produce into the session straight away
+        *session.producer.lock().await = None;
+        sessions.detach_producer(&session_id, &session).await;
+    }
+
+    Some(Json(SessionSummary::new(session_id, &session).await))
+}
+
+/// Removes the session along with its queue, ex when its token was lost
+#[delete("/delete_session/<session_id>")]
+pub(super) async fn delete_session(
+    sessions: &Sessions,
+    session_id: String,
+    _admin: Admin,
+) -> Option<()> {
+    let session = sessions.sessions.write().await.remove(&session_id)?;
+    session.live.store(false, Relaxed);
+    let mut data = session.data.lock().await;
+    data.clear();
+    session.emit(QueueEvent::Cleared);
+    drop(data);
+    session.notify_change.notify_waiters();
+    sessions.session_notify.notify_waiters();
+
+    sessions
+        .store(move |conn| store::delete(conn, &session_id))
+        .await;
+    Some(())
+}
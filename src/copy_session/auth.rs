@@ -0,0 +1,142 @@
+use rocket::response::status::Created;
+use rocket::response::Responder;
+use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::database::search::aliases;
+use crate::Config;
+
+use super::{payload::PayloadError, store, SessionInfo, Sessions};
+
+#[derive(Debug, Serialize, thiserror::Error)]
+pub enum SessionError {
+    #[error("A session with the provided id already exists")]
+    ExistingSession,
+    #[error("'{0}' is not a build location in the config")]
+    UnknownBuildLocation(String),
+    #[error("Failed to generate a session token")]
+    TokenGeneration,
+    #[error("Failed to save the session")]
+    Store,
+    #[error("{0}")]
+    Refused(&'static str),
+    #[error("{0}")]
+    Rejected(PayloadError),
+}
+
+impl<'r> Responder<'r, 'static> for SessionError {
+    fn respond_to(
+        self,
+        _: &'r rocket::Request<'_>,
+    ) -> std::result::Result<rocket::Response<'static>, rocket::http::Status> {
+        use rocket::response::Response;
+        use std::io::Cursor;
+
+        Response::build()
+            .header(rocket::http::ContentType::Plain)
+            .status(rocket::http::Status::BadRequest)
+            .streamed_body(Cursor::new(serde_json::to_vec(&self).unwrap_or_default()))
+            .ok()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NewSession {
+    // only clients at this build location can connect
+    pub build_location: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedSession {
+    pub session_id: String,
+    // has to be presented by every producer and consumer that connects
+    pub token: String,
+    pub build_location: Option<String>,
+}
+
+fn generate_token() -> Option<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).ok()?;
+    Some(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Compares tokens in constant time so how long it takes says nothing about how much
+/// of a guess was right
+fn same_token(given: &str, token: &str) -> bool {
+    let (given, token) = (given.as_bytes(), token.as_bytes());
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+impl Sessions {
+    /// Looks the session up for a client connecting with `token` from `build_location`
+    pub(super) async fn authorize(
+        &self,
+        session_id: &str,
+        token: Option<&str>,
+        build_location: Option<&str>,
+    ) -> Result<Arc<SessionInfo>, &'static str> {
+        let session = self
+            .sessions
+            .read()
+            .await
+            .get(session_id)
+            .cloned()
+            .ok_or("Session does not exist")?;
+        if !token.is_some_and(|token| same_token(token, &session.token)) {
+            return Err("Invalid session token");
+        }
+        if session.build_location.is_some() && session.build_location.as_deref() != build_location {
+            return Err("Session belongs to another build location");
+        }
+        session.touch();
+        Ok(session)
+    }
+}
+
+#[post("/new_session/<session_id>", data = "<new>")]
+pub(super) async fn new_session(
+    sessions: &Sessions,
+    config: &Config,
+    session_id: String,
+    new: Option<Json<NewSession>>,
+) -> Result<Created<Json<CreatedSession>>, SessionError> {
+    let new = new.map(Json::into_inner).unwrap_or_default();
+    let build_location = match new.build_location {
+        Some(location) => Some(
+            aliases::resolve_key(config, "build_locations", &location)
+                .ok_or(SessionError::UnknownBuildLocation(location))?,
+        ),
+        None => None,
+    };
+    let token = generate_token().ok_or(SessionError::TokenGeneration)?;
+
+    let mut lock = sessions.sessions.write().await;
+    if lock.contains_key(&session_id) {
+        return Err(SessionError::ExistingSession);
+    }
+
+    let (id, stored_token, location) = (session_id.clone(), token.clone(), build_location.clone());
+    sessions
+        .store(move |conn| store::create(conn, &id, &stored_token, location.as_deref()))
+        .await
+        .ok_or(SessionError::Store)?;
+
+    lock.insert(
+        session_id.clone(),
+        Arc::new(SessionInfo::new(token.clone(), build_location.clone())),
+    );
+    drop(lock);
+
+    Ok(Created::new("/").body(Json(CreatedSession {
+        session_id,
+        token,
+        build_location,
+    })))
+}
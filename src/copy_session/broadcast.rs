@@ -0,0 +1,251 @@
+use rocket::{
+    futures::{SinkExt, StreamExt},
+    tokio::{select, sync::Mutex},
+    Shutdown,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::{Duration, Instant};
+
+use super::payload::Item;
+use super::Sessions;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    pub seq: u64,
+    pub item: Item,
+}
+
+#[derive(Default)]
+struct State {
+    // sequence number of the last published item
+    last_seq: u64,
+    // published items that some subscriber hasn't acknowledged yet
+    log: VecDeque<Delivery>,
+    // the last sequence number each subscriber acknowledged
+    acked: HashMap<String, u64>,
+    // when each named subscriber that isn't connected went away
+    left: HashMap<String, Instant>,
+    next_anonymous: usize,
+}
+
+impl State {
+    fn trim(&mut self) {
+        match self.acked.values().min().copied() {
+            Some(min) => {
+                while self.log.front().is_some_and(|d| d.seq <= min) {
+                    self.log.pop_front();
+                }
+            }
+            None => self.log.clear(),
+        }
+    }
+}
+
+/// Every item pushed into a session, kept until all of its subscribers acknowledged it
+#[derive(Default)]
+pub struct Broadcast(Mutex<State>);
+
+impl Broadcast {
+    pub async fn publish(&self, item: Item) {
+        let mut state = self.0.lock().await;
+        state.last_seq += 1;
+        if !state.acked.is_empty() {
+            let seq = state.last_seq;
+            state.log.push_back(Delivery { seq, item });
+        }
+    }
+
+    /// Registers a subscriber and returns the last sequence number it acknowledged.
+    /// New subscribers only get items published from now on
+    async fn subscribe(&self, consumer: &str) -> u64 {
+        let mut state = self.0.lock().await;
+        let last_seq = state.last_seq;
+        state.left.remove(consumer);
+        *state.acked.entry(consumer.to_owned()).or_insert(last_seq)
+    }
+
+    /// Named subscribers are kept for the grace period after they go away, see `expire`
+    async fn leave(&self, consumer: &str) {
+        let mut state = self.0.lock().await;
+        state.left.insert(consumer.to_owned(), Instant::now());
+    }
+
+    /// Forgets the named subscribers that have been gone for longer than `grace_period`,
+    /// so the items they never acknowledged aren't kept forever
+    pub async fn expire(&self, grace_period: Duration) {
+        let mut state = self.0.lock().await;
+        let expired: Vec<String> = state
+            .left
+            .iter()
+            .filter(|(_, left)| left.elapsed() >= grace_period)
+            .map(|(consumer, _)| consumer.clone())
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+        for consumer in expired {
+            state.left.remove(&consumer);
+            state.acked.remove(&consumer);
+        }
+        state.trim();
+    }
+
+    pub async fn subscribers(&self) -> usize {
+        self.0.lock().await.acked.len()
+    }
+
+    async fn anonymous(&self) -> String {
+        let mut state = self.0.lock().await;
+        state.next_anonymous += 1;
+        format!("anonymous-{}", state.next_anonymous)
+    }
+
+    async fn unsubscribe(&self, consumer: &str) {
+        let mut state = self.0.lock().await;
+        state.acked.remove(consumer);
+        state.trim();
+    }
+
+    async fn since(&self, seq: u64) -> Vec<Delivery> {
+        let state = self.0.lock().await;
+        state.log.iter().filter(|d| d.seq > seq).cloned().collect()
+    }
+
+    async fn ack(&self, consumer: &str, seq: u64) {
+        let mut state = self.0.lock().await;
+        let seq = seq.min(state.last_seq);
+        if let Some(acked) = state.acked.get_mut(consumer) {
+            *acked = seq.max(*acked);
+        }
+        state.trim();
+    }
+}
+
+/// Unlike `consume_single` every subscriber gets every item. Named consumers are
+/// remembered so unacknowledged items are delivered again when they reconnect
+#[get("/subscribe/<session_id>?<consumer>&<token>&<build_location>")]
+pub async fn session_subscriber<'r>(
+    websocket: ws::WebSocket,
+    sessions: &'r Sessions,
+    session_id: String,
+    consumer: Option<String>,
+    token: Option<&str>,
+    build_location: Option<&str>,
+    shutdown: Shutdown,
+) -> ws::Channel<'r> {
+    #[derive(Serialize)]
+    enum Message {
+        SessionLive(bool),
+        Delivered(Delivery),
+    }
+    #[derive(Deserialize)]
+    enum Request {
+        // acknowledges every item up to and including the sequence number
+        Ack(u64),
+    }
+
+    let session = match sessions.authorize(&session_id, token, build_location).await {
+        Ok(session) => session,
+        Err(reason) => return super::refuse(websocket, reason),
+    };
+
+    websocket.channel(move |mut stream| {
+        Box::pin(async move {
+            let broadcast = &session.broadcast;
+            let (consumer, anonymous) = match consumer {
+                Some(consumer) => (consumer, false),
+                None => (broadcast.anonymous().await, true),
+            };
+            let mut sent = broadcast.subscribe(&consumer).await;
+
+            let ret = async {
+                macro_rules! send {
+                    ($message:expr) => {
+                        let val = serde_json::to_string(&$message).unwrap_or_default();
+                        stream.send(ws::Message::Text(val)).await?;
+                    };
+                }
+
+                send!(Message::SessionLive(session.live.load(Relaxed)));
+                loop {
+                    let shutdown = shutdown.clone();
+                    let changed = session.notify_change.notified();
+                    rocket::tokio::pin!(changed);
+                    changed.as_mut().enable();
+
+                    for delivery in broadcast.since(sent).await {
+                        sent = delivery.seq;
+                        send!(Message::Delivered(delivery));
+                    }
+
+                    select! {
+                        _ = &mut changed => {}
+                        _ = sessions.session_notify.notified() => {
+                            send!(Message::SessionLive(session.live.load(Relaxed)));
+                        }
+                        next_recv = stream.next() => {
+                            match next_recv {
+                                Some(Ok(ws::Message::Text(val))) => {
+                                    if let Ok(Request::Ack(seq)) = serde_json::from_str(&val) {
+                                        broadcast.ack(&consumer, seq).await;
+                                    }
+                                }
+                                Some(Ok(ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                                _ => {}
+                            }
+                        }
+                        _ = shutdown => break,
+                    }
+                }
+                Ok(())
+            }
+            .await;
+
+            if anonymous {
+                broadcast.unsubscribe(&consumer).await;
+            } else {
+                broadcast.leave(&consumer).await;
+            }
+            ret
+        })
+    })
+}
+
+#[rocket::async_test]
+async fn test_broadcast() {
+    let broadcast = Broadcast::default();
+    let item = |data: &str| Item::Data(data.into());
+    let seqs = |deliveries: Vec<Delivery>| deliveries.iter().map(|d| d.seq).collect::<Vec<_>>();
+
+    // nobody is listening, so nothing is kept
+    broadcast.publish(item("a")).await;
+    assert_eq!(broadcast.subscribe("left").await, 1);
+    assert_eq!(broadcast.subscribe("right").await, 1);
+    broadcast.publish(item("b")).await;
+    broadcast.publish(item("c")).await;
+    assert_eq!(seqs(broadcast.since(1).await), [2, 3]);
+
+    // kept until every subscriber acknowledged it
+    broadcast.ack("left", 3).await;
+    broadcast.ack("right", 2).await;
+    assert_eq!(seqs(broadcast.since(0).await), [3]);
+
+    // reconnecting picks up from the last acknowledged item
+    broadcast.leave("right").await;
+    broadcast.publish(item("d")).await;
+    let since = broadcast.subscribe("right").await;
+    assert_eq!(since, 2);
+    assert_eq!(seqs(broadcast.since(since).await), [3, 4]);
+
+    // a subscriber that stays away is dropped along with what it didn't acknowledge
+    broadcast.leave("right").await;
+    broadcast.expire(Duration::from_secs(60)).await;
+    assert_eq!(broadcast.subscribers().await, 2);
+    broadcast.expire(Duration::ZERO).await;
+    assert_eq!(broadcast.subscribers().await, 1);
+    assert_eq!(seqs(broadcast.since(0).await), [4]);
+    broadcast.ack("left", 4).await;
+    assert!(broadcast.since(0).await.is_empty());
+}
@@ -0,0 +1,102 @@
+//! For clients that can't hold a websocket, ex barcode stations and scripts.
+//! They feed and take from the same queue as the websocket routes
+
+use rocket::{
+    response::status::{Accepted, NoContent},
+    serde::json::Json,
+    tokio::{select, time},
+    Shutdown,
+};
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::Config;
+
+use super::auth::SessionError;
+use super::payload::Item;
+use super::protocol::Queued;
+use super::{SessionInfo, Sessions};
+
+// seconds `pop` waits for an item unless told otherwise, and at most
+const DEFAULT_POP_TIMEOUT: u64 = 30;
+const MAX_POP_TIMEOUT: u64 = 120;
+
+#[derive(Debug, Serialize)]
+pub struct Pushed {
+    pub item_id: u64,
+}
+
+/// Queues the body like a message of a version 1 producer, a trailing line break is dropped
+#[post(
+    "/sessions/<session_id>/push?<token>&<build_location>",
+    data = "<data>"
+)]
+pub(super) async fn push(
+    sessions: &Sessions,
+    config: &Config,
+    session_id: String,
+    token: Option<&str>,
+    build_location: Option<&str>,
+    data: String,
+) -> Result<Accepted<Json<Pushed>>, SessionError> {
+    let session = sessions
+        .authorize(&session_id, token, build_location)
+        .await
+        .map_err(SessionError::Refused)?;
+    let data = data.trim_end_matches(['\r', '\n']).to_owned();
+    let item = Item::parse(config, data).map_err(SessionError::Rejected)?;
+
+    let item_id = sessions.push_back(&session_id, &session, item).await;
+    Ok(Accepted(Json(Pushed { item_id })))
+}
+
+async fn next_in_line(sessions: &Sessions, session: &SessionInfo, queue_id: usize) -> Queued {
+    loop {
+        let updated = session.queue_updated.notified();
+        let changed = session.notify_change.notified();
+        rocket::tokio::pin!(updated, changed);
+        updated.as_mut().enable();
+        changed.as_mut().enable();
+
+        if session.position(queue_id).await == 0 {
+            if let Some(queued) = sessions.take_front(session).await {
+                return queued;
+            }
+        }
+        select! {
+            _ = updated => {}
+            _ = changed => {}
+        }
+    }
+}
+
+/// Waits in line with the websocket consumers for up to `timeout` seconds,
+/// responds with 204 if no item came in by then
+#[get("/sessions/<session_id>/pop?<token>&<build_location>&<timeout>")]
+pub(super) async fn pop(
+    sessions: &Sessions,
+    session_id: String,
+    token: Option<&str>,
+    build_location: Option<&str>,
+    timeout: Option<u64>,
+    shutdown: Shutdown,
+) -> Result<Result<Json<Item>, NoContent>, SessionError> {
+    let session = sessions
+        .authorize(&session_id, token, build_location)
+        .await
+        .map_err(SessionError::Refused)?;
+    let timeout = timeout.unwrap_or(DEFAULT_POP_TIMEOUT).min(MAX_POP_TIMEOUT);
+
+    let queue_id = session.join_line().await;
+    let queued = select! {
+        queued = next_in_line(sessions, &session, queue_id) => Some(queued),
+        _ = time::sleep(Duration::from_secs(timeout)) => None,
+        _ = shutdown => None,
+    };
+    session.leave_line(queue_id).await;
+
+    match queued {
+        Some(queued) => Ok(Ok(Json(sessions.ack(&session_id, &session, queued).await))),
+        None => Ok(Err(NoContent)),
+    }
+}
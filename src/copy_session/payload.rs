@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::database::search::{aliases, verify_column, ColumnType};
+use crate::Config;
+
+#[derive(Debug, Clone, Serialize, thiserror::Error)]
+pub enum PayloadError {
+    #[error("'{0}' is not a form field that can be filled from a session")]
+    UnknownField(String),
+    #[error("Expected a {expected} for '{field}' but got {value}")]
+    InvalidType {
+        field: String,
+        expected: &'static str,
+        value: Value,
+    },
+    #[error("{value} for '{field}' doesn't match the pattern '{pattern}'")]
+    PatternMismatch {
+        field: String,
+        pattern: String,
+        value: String,
+    },
+    #[error("{value} is not a value of '{enumeration}' in the config")]
+    UnknownEnumValue {
+        field: String,
+        enumeration: &'static str,
+        value: String,
+    },
+}
+
+/// What producers send, anything that isn't one of these is relayed as plain data
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Payload {
+    Field { field: String, value: Value },
+    // a partial `NewQCForm`, ex every serial printed on one label
+    Form(Map<String, Value>),
+}
+
+/// How version 1 producers mark a typed payload, `{"type": "fields", "data": ...}` with the
+/// same `data` as a version 2 push. Their other text, JSON or not, is relayed as is
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+enum Marked {
+    Fields(Value),
+}
+
+/// A queued entry of a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Item {
+    // pasted into whichever input is consuming
+    Data(String),
+    // form fields mapped onto the matching inputs of the consumer
+    Fields(Map<String, Value>),
+}
+
+impl Item {
+    /// Parses what a version 1 producer sent, typed payloads are checked against the config
+    pub fn parse(config: &Config, text: String) -> Result<Self, PayloadError> {
+        match serde_json::from_str::<Marked>(&text) {
+            Ok(Marked::Fields(data)) => Self::from_value(config, data),
+            Err(_) => Ok(Self::Data(text)),
+        }
+    }
+
+    /// The `data` of a version 2 push, where plain data has to be a string
+    pub fn from_value(config: &Config, value: Value) -> Result<Self, PayloadError> {
+        match value {
+            Value::String(data) => Ok(Self::Data(data)),
+            value => match Payload::deserialize(&value) {
+                Ok(payload) => Self::from_payload(config, payload),
+                Err(_) => Err(PayloadError::InvalidType {
+                    field: "data".into(),
+                    expected: "string or object",
+                    value,
+                }),
+            },
+        }
+    }
+
+    fn from_payload(config: &Config, payload: Payload) -> Result<Self, PayloadError> {
+        let fields = match payload {
+            Payload::Field { field, value } => Map::from_iter([(field, value)]),
+            Payload::Form(form) => form,
+        };
+        Ok(Self::Fields(validate(config, fields)?))
+    }
+
+    /// Reads an entry back from the session store
+    pub fn from_stored(stored: String) -> Self {
+        serde_json::from_str(&stored).unwrap_or(Self::Data(stored))
+    }
+
+    pub fn to_stored(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+fn validate(
+    config: &Config,
+    fields: Map<String, Value>,
+) -> Result<Map<String, Value>, PayloadError> {
+    fields
+        .into_iter()
+        .map(|(field, value)| {
+            let column = verify_column(&field)
+                .ok()
+                .filter(|c| c.column_name != "finalized")
+                .ok_or_else(|| PayloadError::UnknownField(field.clone()))?;
+
+            let value = match (&column.col_type, value) {
+                (ColumnType::Boolean, Value::Bool(bool)) => Value::Bool(bool),
+                (ColumnType::Text, Value::Null) if column.nullable => Value::Null,
+                (ColumnType::Text, Value::String(str)) => {
+                    Value::String(validate_text(config, &field, str)?)
+                }
+                (ColumnType::Boolean, value) => {
+                    return Err(PayloadError::InvalidType {
+                        field,
+                        expected: "boolean",
+                        value,
+                    })
+                }
+                (ColumnType::Text, value) => {
+                    return Err(PayloadError::InvalidType {
+                        field,
+                        expected: "string",
+                        value,
+                    })
+                }
+                _ => return Err(PayloadError::UnknownField(field)),
+            };
+            Ok((field, value))
+        })
+        .collect()
+}
+
+fn validate_text(config: &Config, field: &str, value: String) -> Result<String, PayloadError> {
+    if let Some(enumeration) = aliases::enumeration(field) {
+        return match aliases::resolve_key(config, enumeration, &value) {
+            Some(key) => Ok(key),
+            None => Err(PayloadError::UnknownEnumValue {
+                field: field.to_owned(),
+                enumeration,
+                value,
+            }),
+        };
+    }
+    if let Some(pattern) = config.pattern(field) {
+        if !pattern.matches(&value) {
+            return Err(PayloadError::PatternMismatch {
+                field: field.to_owned(),
+                pattern: pattern.pattern.clone(),
+                value,
+            });
+        }
+    }
+    Ok(value)
+}
+
+#[test]
+fn test_payloads() {
+    let config = Config::load_from_file("./config.json5").expect("Failed to load config file");
+
+    assert!(matches!(
+        Item::parse(&config, "SHIL-0023746".into()),
+        Ok(Item::Data(data)) if data == "SHIL-0023746"
+    ));
+    assert!(matches!(
+        Item::parse(&config, "12345".into()),
+        Ok(Item::Data(_))
+    ));
+
+    let Ok(Item::Fields(fields)) = Item::parse(
+        &config,
+        r#"{"type": "fields", "data": {"item_serial": "SHIL-0023746", "asm_serial": "CFS-SL300F-001220", "ram_size": "16 GiB"}}"#
+            .into(),
+    ) else {
+        panic!("expected fields");
+    };
+    assert_eq!(fields["ram_size"], "GiB016");
+
+    assert!(matches!(
+        Item::parse(
+            &config,
+            r#"{"type": "fields", "data": {"field": "oem_serial", "value": " padded"}}"#.into()
+        ),
+        Err(PayloadError::PatternMismatch { .. })
+    ));
+    assert!(matches!(
+        Item::parse(
+            &config,
+            r#"{"type": "fields", "data": {"field": "asm_serial", "value": "CFS-SL300F-001220x"}}"#
+                .into()
+        ),
+        Err(PayloadError::PatternMismatch { .. })
+    ));
+    assert!(matches!(
+        Item::parse(
+            &config,
+            r#"{"type": "fields", "data": {"field": "finalized", "value": true}}"#.into()
+        ),
+        Err(PayloadError::UnknownField(_))
+    ));
+    assert!(matches!(
+        Item::parse(
+            &config,
+            r#"{"type": "fields", "data": {"mso_installed": "yes"}}"#.into()
+        ),
+        Err(PayloadError::InvalidType { .. })
+    ));
+    // JSON from producers that predate typed payloads is still opaque
+    assert!(matches!(
+        Item::parse(&config, r#"{"mso_installed": "yes"}"#.into()),
+        Ok(Item::Data(data)) if data == r#"{"mso_installed": "yes"}"#
+    ));
+
+    assert!(matches!(
+        Item::from_value(&config, serde_json::json!("{\"ram_size\": 16}")),
+        Ok(Item::Data(_))
+    ));
+    assert!(matches!(
+        Item::from_value(&config, serde_json::json!({"field": "ram_size", "value": "16 GiB"})),
+        Ok(Item::Fields(fields)) if fields["ram_size"] == "GiB016"
+    ));
+    assert!(matches!(
+        Item::from_value(&config, serde_json::json!(12345)),
+        Err(PayloadError::InvalidType { .. })
+    ));
+}
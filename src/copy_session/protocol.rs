@@ -0,0 +1,415 @@
+//! Version 2 of the session websockets, selected with `?version=2`. Queued items
+//! have ids, producers get incremental queue updates instead of the whole queue
+//! and consumers have to ack what they were delivered, anything they don't ack
+//! in time is queued again
+
+use rocket::{
+    futures::{SinkExt, StreamExt},
+    tokio::{
+        select,
+        sync::broadcast::error::RecvError,
+        time::{self, Instant, Interval, MissedTickBehavior},
+    },
+    Shutdown,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::Duration;
+use ws::stream::DuplexStream;
+
+use crate::Config;
+
+use super::payload::{Item, PayloadError};
+use super::{SessionInfo, Sessions, Timeouts};
+
+/// The newest protocol version, 1 is the original one
+pub const VERSION: u8 = 2;
+
+// how many `msg_id`s of a session are remembered to ignore retried pushes
+const RECENT_PUSHES: usize = 256;
+
+/// An entry of a session queue
+#[derive(Debug, Clone, Serialize)]
+pub struct Queued {
+    pub id: u64,
+    // row in the session store, `None` if storing the item failed
+    #[serde(skip)]
+    pub row: Option<i32>,
+    pub item: Item,
+}
+
+/// Sent to producers whenever the queue changes
+#[derive(Debug, Clone, Serialize)]
+pub enum QueueEvent {
+    Added { index: usize, queued: Queued },
+    Removed(u64),
+    Cleared,
+}
+
+#[derive(Serialize)]
+enum ServerMessage {
+    Hello {
+        version: u8,
+        heartbeat_secs: u64,
+        ack_timeout_secs: u64,
+    },
+    // the whole queue, sent once a producer attached or after it missed updates
+    Snapshot(Vec<Queued>),
+    Update(QueueEvent),
+    Accepted {
+        msg_id: u64,
+        item_id: u64,
+    },
+    Rejected {
+        msg_id: u64,
+        error: PayloadError,
+    },
+    QueuePos {
+        position: usize,
+        live: bool,
+    },
+    Deliver(Queued),
+    Pong,
+    Error(String),
+}
+
+#[derive(Deserialize)]
+enum ClientMessage {
+    // producers only, pushing the same `msg_id` again doesn't queue the item twice
+    Push { msg_id: u64, data: Value },
+    // consumers only, with the id of the delivered item
+    Ack(u64),
+    Nack(u64),
+    // for clients that can't send websocket pings, ex browsers
+    Ping,
+}
+
+/// Pings the client every `period`, two periods without hearing from it and it's gone
+struct Heartbeat {
+    period: Duration,
+    interval: Interval,
+    last_seen: Instant,
+}
+
+impl Heartbeat {
+    fn new(period: Duration) -> Self {
+        let period = period.max(Duration::from_secs(1));
+        let mut interval = time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            period,
+            interval,
+            last_seen: Instant::now(),
+        }
+    }
+
+    fn seen(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    /// Waits for the next beat, `false` once the client stopped responding
+    async fn beat(&mut self) -> bool {
+        self.interval.tick().await;
+        self.last_seen.elapsed() < self.period * 2
+    }
+}
+
+impl SessionInfo {
+    /// Has to be called while holding the `data` lock so events arrive in queue order
+    pub(super) fn emit(&self, event: QueueEvent) {
+        // fails when no producer is listening
+        let _ = self.events.send(event);
+    }
+
+    async fn subscribe(
+        &self,
+    ) -> (
+        Vec<Queued>,
+        rocket::tokio::sync::broadcast::Receiver<QueueEvent>,
+    ) {
+        let data = self.data.lock().await;
+        (data.iter().cloned().collect(), self.events.subscribe())
+    }
+
+    fn pushed(&self, pusher: &str, msg_id: u64) -> Option<u64> {
+        let recent = self.recent_pushes.lock().ok()?;
+        recent
+            .iter()
+            .find(|(p, m, _)| p == pusher && *m == msg_id)
+            .map(|(_, _, item_id)| *item_id)
+    }
+
+    fn remember_push(&self, pusher: &str, msg_id: u64, item_id: u64) {
+        if let Ok(mut recent) = self.recent_pushes.lock() {
+            if recent.len() >= RECENT_PUSHES {
+                recent.pop_front();
+            }
+            recent.push_back((pusher.to_owned(), msg_id, item_id));
+        }
+    }
+
+    /// Lines a consumer up for the queue, the one at position 0 gets the next item
+    pub(super) async fn join_line(&self) -> usize {
+        let queue_id = self.next_queue_id.fetch_add(1, Relaxed);
+        self.queue_info.write().await.push(queue_id);
+        self.queue_updated.notify_waiters();
+        queue_id
+    }
+
+    pub(super) async fn position(&self, queue_id: usize) -> usize {
+        self.queue_info
+            .read()
+            .await
+            .iter()
+            .position(|i| *i == queue_id)
+            .unwrap_or_default()
+    }
+
+    pub(super) async fn leave_line(&self, queue_id: usize) {
+        let mut lock = self.queue_info.write().await;
+        if let Some(pos) = lock.iter().position(|i| *i == queue_id) {
+            lock.remove(pos);
+        }
+        drop(lock);
+        self.queue_updated.notify_waiters();
+    }
+}
+
+fn hello(timeouts: Timeouts) -> ServerMessage {
+    ServerMessage::Hello {
+        version: VERSION,
+        heartbeat_secs: timeouts.heartbeat.as_secs(),
+        ack_timeout_secs: timeouts.ack_timeout.as_secs(),
+    }
+}
+
+async fn send(stream: &mut DuplexStream, message: &ServerMessage) -> ws::result::Result<()> {
+    let val = serde_json::to_string(message).unwrap_or_default();
+    stream.send(ws::Message::Text(val)).await
+}
+
+async fn close(
+    stream: &mut DuplexStream,
+    code: ws::frame::CloseCode,
+    reason: &'static str,
+) -> ws::result::Result<()> {
+    stream
+        .close(Some(ws::frame::CloseFrame {
+            code,
+            reason: reason.into(),
+        }))
+        .await
+}
+
+async fn push(
+    sessions: &Sessions,
+    config: &Config,
+    session_id: &str,
+    session: &SessionInfo,
+    pusher: &str,
+    msg_id: u64,
+    data: Value,
+) -> ServerMessage {
+    if let Some(item_id) = session.pushed(pusher, msg_id) {
+        return ServerMessage::Accepted { msg_id, item_id };
+    }
+    match Item::from_value(config, data) {
+        Ok(item) => {
+            let item_id = sessions.push_back(session_id, session, item).await;
+            session.remember_push(pusher, msg_id, item_id);
+            ServerMessage::Accepted { msg_id, item_id }
+        }
+        Err(error) => ServerMessage::Rejected { msg_id, error },
+    }
+}
+
+/// Runs until the producer leaves or is kicked, the caller detaches it.
+/// `pusher` scopes its `msg_id`s
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn produce(
+    stream: &mut DuplexStream,
+    sessions: &Sessions,
+    config: &Config,
+    session_id: &str,
+    session: &SessionInfo,
+    attachment: usize,
+    pusher: &str,
+    shutdown: Shutdown,
+) -> ws::result::Result<()> {
+    send(stream, &hello(sessions.timeouts)).await?;
+    let (snapshot, mut events) = session.subscribe().await;
+    send(stream, &ServerMessage::Snapshot(snapshot)).await?;
+
+    let mut heartbeat = Heartbeat::new(sessions.timeouts.heartbeat);
+    while session.live.load(Relaxed) && session.attachments.load(Relaxed) == attachment {
+        let shutdown = shutdown.clone();
+        select! {
+            next_recv = stream.next() => {
+                let Some(next_recv) = next_recv else { break };
+                heartbeat.seen();
+                match next_recv? {
+                    ws::Message::Text(val) => {
+                        let reply = match serde_json::from_str(&val) {
+                            Ok(ClientMessage::Push { msg_id, data }) => {
+                                push(sessions, config, session_id, session, pusher, msg_id, data).await
+                            }
+                            Ok(ClientMessage::Ping) => ServerMessage::Pong,
+                            Ok(_) => ServerMessage::Error("Only consumers ack items".into()),
+                            Err(err) => ServerMessage::Error(err.to_string()),
+                        };
+                        send(stream, &reply).await?;
+                    }
+                    ws::Message::Close(_) => break,
+                    _ => {}
+                }
+            }
+            event = events.recv() => match event {
+                Ok(event) => send(stream, &ServerMessage::Update(event)).await?,
+                Err(RecvError::Lagged(_)) => {
+                    let snapshot;
+                    (snapshot, events) = session.subscribe().await;
+                    send(stream, &ServerMessage::Snapshot(snapshot)).await?;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // wakes the loop up when the producer is kicked
+            _ = session.notify_change.notified() => {}
+            alive = heartbeat.beat() => {
+                if !alive {
+                    return close(stream, ws::frame::CloseCode::Away, "Heartbeat timeout").await;
+                }
+                stream.send(ws::Message::Ping(Vec::new())).await?;
+            }
+            _ = shutdown => break,
+        }
+    }
+    Ok(())
+}
+
+/// Waits in line for one item and keeps it in flight until the consumer acks it.
+/// Once the ack timeout passes, or the consumer nacks it or goes away, the item
+/// is put back at the front of the queue
+pub(super) async fn consume(
+    stream: &mut DuplexStream,
+    sessions: &Sessions,
+    session_id: &str,
+    session: &SessionInfo,
+    shutdown: Shutdown,
+) -> ws::result::Result<()> {
+    send(stream, &hello(sessions.timeouts)).await?;
+
+    let queue_id = session.join_line().await;
+
+    let mut heartbeat = Heartbeat::new(sessions.timeouts.heartbeat);
+    let mut in_flight = None;
+    let ret = async {
+        let mut sent = None;
+        let queued = loop {
+            let shutdown = shutdown.clone();
+            let updated = session.queue_updated.notified();
+            let changed = session.notify_change.notified();
+            let live_changed = sessions.session_notify.notified();
+            rocket::tokio::pin!(updated, changed, live_changed);
+            updated.as_mut().enable();
+            changed.as_mut().enable();
+            live_changed.as_mut().enable();
+
+            let position = session.position(queue_id).await;
+            if position == 0 {
+                if let Some(queued) = sessions.take_front(session).await {
+                    break queued;
+                }
+            }
+            let live = session.live.load(Relaxed);
+            if sent != Some((position, live)) {
+                sent = Some((position, live));
+                send(stream, &ServerMessage::QueuePos { position, live }).await?;
+            }
+
+            select! {
+                _ = updated => {}
+                _ = changed => {}
+                _ = live_changed => {}
+                next_recv = stream.next() => {
+                    let Some(next_recv) = next_recv else { return Ok(()) };
+                    heartbeat.seen();
+                    match next_recv? {
+                        ws::Message::Text(val) => {
+                            let reply = match serde_json::from_str(&val) {
+                                Ok(ClientMessage::Ping) => ServerMessage::Pong,
+                                Ok(_) => ServerMessage::Error("Nothing was delivered yet".into()),
+                                Err(err) => ServerMessage::Error(err.to_string()),
+                            };
+                            send(stream, &reply).await?;
+                        }
+                        ws::Message::Close(_) => return Ok(()),
+                        _ => {}
+                    }
+                }
+                alive = heartbeat.beat() => {
+                    if !alive {
+                        return close(stream, ws::frame::CloseCode::Away, "Heartbeat timeout").await;
+                    }
+                    stream.send(ws::Message::Ping(Vec::new())).await?;
+                }
+                _ = shutdown => return Ok(()),
+            }
+        };
+        // the next consumer can wait for the following item in the meantime
+        session.leave_line(queue_id).await;
+
+        let item_id = queued.id;
+        in_flight = Some(queued.clone());
+        send(stream, &ServerMessage::Deliver(queued)).await?;
+
+        let deadline = time::sleep(sessions.timeouts.ack_timeout);
+        rocket::tokio::pin!(deadline);
+        loop {
+            let shutdown = shutdown.clone();
+            select! {
+                _ = &mut deadline => return Ok(()),
+                next_recv = stream.next() => {
+                    let Some(next_recv) = next_recv else { return Ok(()) };
+                    heartbeat.seen();
+                    match next_recv? {
+                        ws::Message::Text(val) => {
+                            let reply = match serde_json::from_str(&val) {
+                                Ok(ClientMessage::Ack(id)) if id == item_id => {
+                                    if let Some(queued) = in_flight.take() {
+                                        sessions.ack(session_id, session, queued).await;
+                                    }
+                                    return close(stream, ws::frame::CloseCode::Normal, "Acked").await;
+                                }
+                                Ok(ClientMessage::Nack(id)) if id == item_id => return Ok(()),
+                                Ok(ClientMessage::Ping) => ServerMessage::Pong,
+                                Ok(ClientMessage::Push { .. }) => {
+                                    ServerMessage::Error("Only producers push items".into())
+                                }
+                                Ok(_) => ServerMessage::Error(format!("Item {item_id} was delivered")),
+                                Err(err) => ServerMessage::Error(err.to_string()),
+                            };
+                            send(stream, &reply).await?;
+                        }
+                        ws::Message::Close(_) => return Ok(()),
+                        _ => {}
+                    }
+                }
+                alive = heartbeat.beat() => {
+                    if !alive {
+                        return close(stream, ws::frame::CloseCode::Away, "Heartbeat timeout").await;
+                    }
+                    stream.send(ws::Message::Ping(Vec::new())).await?;
+                }
+                _ = shutdown => return Ok(()),
+            }
+        }
+    }
+    .await;
+
+    session.leave_line(queue_id).await;
+    if let Some(queued) = in_flight {
+        sessions.requeue(session, queued).await;
+    }
+    ret
+}
@@ -0,0 +1,149 @@
+use rocket_sync_db_pools::diesel;
+
+use self::diesel::prelude::*;
+
+use crate::database::schema::{copy_session_items, copy_sessions};
+use crate::database::time_default;
+use crate::time::Time;
+
+diesel::sql_function! {
+    fn last_insert_rowid() -> diesel::sql_types::Integer;
+}
+
+#[derive(Debug, Queryable)]
+pub struct StoredSession {
+    pub session_id: String,
+    pub producer: Option<String>,
+    // when the producer went away, `None` if it was still connected
+    pub disconnected: Option<Time>,
+    pub token: String,
+    pub build_location: Option<String>,
+    pub last_active: Option<Time>,
+}
+
+pub fn create(
+    conn: &mut SqliteConnection,
+    session_id: &str,
+    token: &str,
+    build_location: Option<&str>,
+) -> QueryResult<()> {
+    diesel::insert_into(copy_sessions::table)
+        .values((
+            copy_sessions::session_id.eq(session_id),
+            copy_sessions::token.eq(token),
+            copy_sessions::build_location.eq(build_location),
+            copy_sessions::last_active.eq(Some(time_default())),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Removes the session along with its queue
+pub fn delete(conn: &mut SqliteConnection, session_id: &str) -> QueryResult<()> {
+    conn.transaction(|conn| {
+        diesel::delete(copy_sessions::table.find(session_id)).execute(conn)?;
+        clear(conn, session_id)
+    })
+}
+
+fn touch(conn: &mut SqliteConnection, session_id: &str) -> QueryResult<()> {
+    diesel::update(copy_sessions::table.find(session_id))
+        .set(copy_sessions::last_active.eq(Some(time_default())))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Every stored session along with its queued items
+#[allow(clippy::type_complexity)]
+pub fn load_all(
+    conn: &mut SqliteConnection,
+) -> QueryResult<Vec<(StoredSession, Vec<(i32, String)>)>> {
+    let stored: Vec<StoredSession> = copy_sessions::table.load(conn)?;
+    stored
+        .into_iter()
+        .map(|session| {
+            let items = load_items(conn, &session.session_id)?;
+            Ok((session, items))
+        })
+        .collect()
+}
+
+/// The row and data of every queued item, including the ones a consumer hasn't acked yet
+pub fn load_items(
+    conn: &mut SqliteConnection,
+    session_id: &str,
+) -> QueryResult<Vec<(i32, String)>> {
+    copy_session_items::table
+        .filter(copy_session_items::session_id.eq(session_id))
+        .order(copy_session_items::id.asc())
+        .select((copy_session_items::id, copy_session_items::data))
+        .load(conn)
+}
+
+/// Marks the session as having a connected producer
+pub fn attach(
+    conn: &mut SqliteConnection,
+    session_id: &str,
+    producer: Option<&str>,
+) -> QueryResult<()> {
+    diesel::update(copy_sessions::table.find(session_id))
+        .set((
+            copy_sessions::producer.eq(producer),
+            copy_sessions::disconnected.eq(None::<Time>),
+            copy_sessions::last_active.eq(Some(time_default())),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+pub fn detach(conn: &mut SqliteConnection, session_id: &str) -> QueryResult<()> {
+    diesel::update(copy_sessions::table.find(session_id))
+        .set(copy_sessions::disconnected.eq(Some(time_default())))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Returns the row of the new item
+pub fn push(conn: &mut SqliteConnection, session_id: &str, data: &str) -> QueryResult<i32> {
+    conn.transaction(|conn| {
+        diesel::insert_into(copy_session_items::table)
+            .values((
+                copy_session_items::session_id.eq(session_id),
+                copy_session_items::data.eq(data),
+            ))
+            .execute(conn)?;
+        let row = diesel::select(last_insert_rowid()).get_result(conn)?;
+        touch(conn, session_id)?;
+        Ok(row)
+    })
+}
+
+/// Removes an item once it was consumed, or deleted by an admin
+pub fn remove(conn: &mut SqliteConnection, session_id: &str, row: i32) -> QueryResult<()> {
+    diesel::delete(copy_session_items::table.find(row)).execute(conn)?;
+    touch(conn, session_id)
+}
+
+/// Drops the queue of a session, unless its producer re-attached in the meantime.
+/// The session itself stays until it expires
+pub fn discard(conn: &mut SqliteConnection, session_id: &str) -> QueryResult<bool> {
+    conn.transaction(|conn| {
+        let detached = diesel::update(
+            copy_sessions::table
+                .filter(copy_sessions::session_id.eq(session_id))
+                .filter(copy_sessions::disconnected.is_not_null()),
+        )
+        .set(copy_sessions::producer.eq(None::<String>))
+        .execute(conn)?;
+        if detached > 0 {
+            clear(conn, session_id)?;
+        }
+        Ok(detached > 0)
+    })
+}
+
+pub fn clear(conn: &mut SqliteConnection, session_id: &str) -> QueryResult<()> {
+    diesel::delete(copy_session_items::table.filter(copy_session_items::session_id.eq(session_id)))
+        .execute(conn)?;
+    Ok(())
+}
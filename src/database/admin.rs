@@ -1,17 +1,26 @@
 use crate::admin_pwd::Admin;
+use crate::{LiveConfig, SharedConfig};
 
 use rocket::serde::json::Json;
+use rocket::State;
 
 use rocket_sync_db_pools::diesel;
 
 use self::diesel::prelude::*;
 
+use super::checklist::CurrentChecklist;
 use super::*;
 
 #[delete("/delete_post/<id>")]
 pub(super) async fn delete_post(db: Db, id: i32, _admin: Admin) -> Result<()> {
     db.run(move |conn| {
+        let sales_order: Option<String> = qc_forms::table
+            .find(id)
+            .select(qc_forms::sales_order)
+            .get_result(conn)?;
+        diesel::delete(unit_events::table.filter(unit_events::form_id.eq(id))).execute(conn)?;
         diesel::delete(qc_forms::table.find(id)).execute(conn)?;
+        sales_order::refresh(conn, sales_order.as_deref())?;
         Ok(())
     })
     .await
@@ -20,17 +29,31 @@ pub(super) async fn delete_post(db: Db, id: i32, _admin: Admin) -> Result<()> {
 #[post("/definalize_post/<id>")]
 pub(super) async fn definalize_post(
     db: Db,
+    config: SharedConfig,
     id: i32,
     _admin: Admin,
-) -> Result<Json<ExistingQCForm>> {
+) -> Result<Json<SummarizedQCForm>> {
     db.run(move |conn| {
         diesel::update(qc_forms::table.find(id))
             .set(qc_forms::finalized.eq(false))
             .execute(conn)?;
-        Ok(qc_forms::table
+        let form = qc_forms::table
             .find(id)
-            .get_result::<ExistingQCForm>(conn)?
-            .into())
+            .get_result::<ExistingQCForm>(conn)?;
+        sales_order::refresh(conn, form.sales_order.as_deref())?;
+        Ok(SummarizedQCForm::load(conn, &config, form)?.into())
     })
     .await
 }
+
+/// Puts the config file into use without a restart, see `checklist::reload`
+#[post("/reload_config")]
+pub(super) async fn reload_config(
+    db: Db,
+    live: &State<LiveConfig>,
+    current: &State<CurrentChecklist>,
+    _admin: Admin,
+) -> Result<Json<serde_json::Value>> {
+    let id = checklist::reload(&db, live, current).await?;
+    Ok(Json(serde_json::json!({ "checklist_version": id })))
+}
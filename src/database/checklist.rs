@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::json_text::JsonText;
+use crate::{Config, LiveConfig, SharedConfig};
+
+use rocket::fairing::AdHoc;
+use rocket::serde::{json::Json, Serialize};
+use rocket::tokio::sync::Mutex;
+use rocket::{Build, Rocket};
+
+use rocket_sync_db_pools::diesel;
+use serde_json::Value;
+
+use crate::time::Time;
+
+use self::diesel::prelude::*;
+
+use super::*;
+
+/// A snapshot of `qc_checks` from the config, forms keep showing the questions
+/// of the version they were created with even after the config changes
+#[derive(Debug, Clone, Serialize, Queryable)]
+#[serde(crate = "rocket::serde")]
+pub struct ChecklistVersion {
+    pub id: i32,
+    pub definition: JsonText,
+    pub creation_date: Time,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = super::schema::checklist_versions)]
+struct NewChecklistVersion {
+    definition: String,
+    creation_date: Time,
+}
+
+/// The versions matching the config, new forms are pinned to the one of their build
+/// location. Clones share the ids so a reload is seen everywhere
+#[derive(Debug, Clone)]
+pub struct CurrentChecklist {
+    ids: Arc<RwLock<ChecklistIds>>,
+    // held for a whole reload so two of them can't put their configs into use out of order
+    reloading: Arc<Mutex<()>>,
+}
+
+/// The version of the checklist with the overrides of each location, locations
+/// without their own checklist use `base`
+#[derive(Debug, Clone, Default)]
+pub struct ChecklistIds {
+    pub base: i32,
+    pub locations: HashMap<String, i32>,
+}
+
+impl CurrentChecklist {
+    pub fn new(ids: ChecklistIds) -> Self {
+        Self {
+            ids: Arc::new(RwLock::new(ids)),
+            reloading: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn get(&self, location: &str) -> i32 {
+        let ids = self.ids.read().unwrap_or_else(PoisonError::into_inner);
+        ids.locations.get(location).copied().unwrap_or(ids.base)
+    }
+
+    /// Puts the config into use along with its versions, nothing reads the versions
+    /// while only one of them is swapped
+    fn swap(&self, live: &LiveConfig, config: Config, ids: ChecklistIds) {
+        let mut current = self.ids.write().unwrap_or_else(PoisonError::into_inner);
+        live.swap(Arc::new(config));
+        *current = ids;
+    }
+}
+
+/// Stores the checklist from the config as a new version unless it is unchanged
+pub fn register(conn: &mut diesel::SqliteConnection, config: &Config) -> Result<i32> {
+    let definition = config
+        .typed()
+        .map(|model| model.qc_checks.definition.clone())
+        .ok_or_else(|| DataBaseError::InvalidConfig(vec!["qc_checks doesn't parse".into()]))?;
+
+    conn.transaction(|conn| {
+        let existing = checklist_versions::table
+            .filter(checklist_versions::definition.eq(&definition))
+            .select(checklist_versions::id)
+            .first::<i32>(conn)
+            .optional()?;
+
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                diesel::insert_into(checklist_versions::table)
+                    .values(NewChecklistVersion {
+                        definition,
+                        creation_date: time_default(),
+                    })
+                    .execute(conn)?;
+                checklist_versions::table
+                    .select(checklist_versions::id)
+                    .order(checklist_versions::id.desc())
+                    .first(conn)?
+            }
+        };
+        Ok(id)
+    })
+}
+
+/// Pins forms from before versioning existed to the version of their build location
+fn pin_unversioned(conn: &mut diesel::SqliteConnection, ids: &ChecklistIds) -> Result<()> {
+    conn.transaction(|conn| {
+        for (location, id) in &ids.locations {
+            diesel::update(
+                qc_forms::table
+                    .filter(qc_forms::checklist_version.is_null())
+                    .filter(qc_forms::build_location.eq(location)),
+            )
+            .set(qc_forms::checklist_version.eq(id))
+            .execute(conn)?;
+        }
+        diesel::update(qc_forms::table.filter(qc_forms::checklist_version.is_null()))
+            .set(qc_forms::checklist_version.eq(ids.base))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+/// The config for the location of a form with `qc_checks` swapped for the pinned
+/// version, which is what templates and validation of an existing form should see
+pub fn pinned(
+    conn: &mut diesel::SqliteConnection,
+    config: &Config,
+    location: &str,
+    version: Option<i32>,
+) -> Result<Config> {
+    let mut items = config.for_location(location).0.clone();
+    if let Some(version) = version {
+        let definition: JsonText = checklist_versions::table
+            .find(version)
+            .select(checklist_versions::definition)
+            .first(conn)?;
+        items["qc_checks"] = definition.0;
+    }
+    Ok(Config::new(items))
+}
+
+impl Db {
+    pub async fn pinned_config(
+        &self,
+        config: SharedConfig,
+        location: String,
+        version: Option<i32>,
+    ) -> Result<Config> {
+        self.run(move |conn| pinned(conn, &config, &location, version))
+            .await
+    }
+}
+
+pub(super) async fn register_current(rocket: Rocket<Build>) -> rocket::fairing::Result {
+    let Some(live) = rocket.state::<LiveConfig>() else {
+        rocket::error!("Checklist versions need the config to be loaded first");
+        return Err(rocket);
+    };
+    let config = live.current();
+
+    let (config, ids) = Db::get_one(&rocket)
+        .await
+        .expect("database connection")
+        .run(move |conn| load(conn, &config))
+        .await
+        .expect("registering checklist version");
+
+    live.swap(Arc::new(config));
+    Ok(rocket.manage(CurrentChecklist::new(ids)))
+}
+
+/// The config file with the changes made through the api and the versions of its checklists
+fn load(conn: &mut diesel::SqliteConnection, config: &Config) -> Result<(Config, ChecklistIds)> {
+    let config = config_change::effective(conn, config)?;
+    let mut ids = ChecklistIds {
+        base: register(conn, &config)?,
+        ..Default::default()
+    };
+    let locations = config.typed().map(|model| &model.location_overrides);
+    for location in locations.into_iter().flat_map(|l| l.keys()) {
+        let id = register(conn, config.for_location(location))?;
+        ids.locations.insert(location.clone(), id);
+    }
+    pin_unversioned(conn, &ids)?;
+    Ok((config, ids))
+}
+
+/// Puts the config file into use once it is valid and its checklists are registered,
+/// when either fails the previous config stays in use. Returns the version without
+/// location overrides
+pub async fn reload(db: &Db, live: &LiveConfig, current: &CurrentChecklist) -> Result<i32> {
+    let _reloading = current.reloading.lock().await;
+    let config = live
+        .read()
+        .map_err(|problems| DataBaseError::InvalidConfig(problems.0))?;
+    let (config, ids) = db.run(move |conn| load(conn, &config)).await?;
+
+    let id = ids.base;
+    current.swap(live, config, ids);
+    Ok(id)
+}
+
+/// Reloads the config whenever the file is modified, checked every
+/// `config_reload_interval` seconds, 0 turns it off
+pub(super) fn watch_config() -> AdHoc {
+    AdHoc::on_liftoff("Config Reload", |rocket| {
+        Box::pin(async move {
+            let seconds = rocket
+                .figment()
+                .extract_inner::<u64>("config_reload_interval")
+                .unwrap_or(5);
+            if seconds == 0 {
+                return;
+            }
+            let (Some(pool), Some(live), Some(current)) = (
+                Db::pool(rocket).cloned(),
+                rocket.state::<LiveConfig>().cloned(),
+                rocket.state::<CurrentChecklist>().cloned(),
+            ) else {
+                rocket::error!("Config reloading needs the database and config to be loaded");
+                return;
+            };
+            let shutdown = rocket.shutdown();
+
+            let modified = |path: &std::path::Path| -> Option<SystemTime> {
+                std::fs::metadata(path).and_then(|m| m.modified()).ok()
+            };
+            let mut last_modified = modified(live.path());
+
+            rocket::tokio::spawn(async move {
+                let mut interval = rocket::tokio::time::interval(Duration::from_secs(seconds));
+                loop {
+                    let shutdown = shutdown.clone();
+                    rocket::tokio::pin!(shutdown);
+
+                    rocket::tokio::select! {
+                        _ = shutdown => return,
+                        _ = interval.tick() => {}
+                    }
+
+                    let now_modified = modified(live.path());
+                    if now_modified == last_modified {
+                        continue;
+                    }
+                    // a broken file is only tried again once it changes
+                    last_modified = now_modified;
+
+                    let Some(db) = pool.get().await.map(Db) else {
+                        rocket::error!("No database connection to reload the config with");
+                        continue;
+                    };
+                    match reload(&db, &live, &current).await {
+                        Ok(id) => rocket::info!("Reloaded config, checklist version {id}"),
+                        Err(err) => rocket::error!("Keeping the previous config: {err}"),
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[get("/checklists")]
+pub(super) async fn list_checklists(db: Db) -> Result<Json<Vec<Value>>> {
+    db.run(move |conn| {
+        let versions: Vec<(i32, Time)> = checklist_versions::table
+            .select((checklist_versions::id, checklist_versions::creation_date))
+            .order(checklist_versions::id.asc())
+            .load(conn)?;
+        Ok(Json(
+            versions
+                .into_iter()
+                .map(|(id, creation_date)| {
+                    serde_json::json!({"id": id, "creation_date": creation_date})
+                })
+                .collect(),
+        ))
+    })
+    .await
+}
+
+#[get("/checklists/<id>")]
+pub(super) async fn get_checklist(db: Db, id: i32) -> Result<Json<ChecklistVersion>> {
+    db.run(move |conn| Ok(Json(checklist_versions::table.find(id).first(conn)?)))
+        .await
+}
@@ -0,0 +1,463 @@
+use crate::admin_pwd::Admin;
+use crate::json_text::JsonText;
+use crate::{Config, LiveConfig};
+
+use rocket::response::status::Created;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::State;
+
+use rocket_sync_db_pools::diesel;
+use serde_json::Value;
+
+use crate::time::Time;
+
+use self::diesel::dsl::sql;
+use self::diesel::prelude::*;
+use self::diesel::sql_types::{Bool, Text};
+
+use super::checklist::{self, CurrentChecklist};
+use super::search::aliases;
+use super::*;
+
+/// The target of changes to `qc_checks.questions`, every other target is an enumeration
+pub const QUESTIONS: &str = "questions";
+
+/// An edit made by an admin to an enumeration or to the checklist questions. Edits are
+/// stored instead of rewriting `config.json5` so its comments survive, and are replayed
+/// on top of the file every time it is loaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "action", rename_all = "snake_case")]
+pub enum ConfigChange {
+    /// adds a value to the end of `order`, questions go to the end of the `tech_form`
+    /// (and `pdf_form`) section with the heading `section`
+    Add {
+        target: String,
+        key: String,
+        value: Value,
+        #[serde(default)]
+        section: Option<String>,
+    },
+    /// changes the `name` of a value or the text of a question
+    Rename {
+        target: String,
+        key: String,
+        name: String,
+    },
+    /// hidden values aren't offered on new forms but stay valid for old ones and searches
+    Hide {
+        target: String,
+        key: String,
+        #[serde(default = "hide_default")]
+        hidden: bool,
+    },
+    /// the same keys in a new order, for questions the ones of the `section`
+    Reorder {
+        target: String,
+        #[serde(default)]
+        section: Option<String>,
+        order: Vec<String>,
+    },
+    /// refused while a form still uses the value or has an answer to the question
+    Delete { target: String, key: String },
+}
+
+fn hide_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+#[serde(crate = "rocket::serde")]
+pub struct StoredConfigChange {
+    pub id: i32,
+    pub change: JsonText,
+    pub creation_date: Time,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = super::schema::config_changes)]
+struct NewConfigChange {
+    change: String,
+    creation_date: Time,
+}
+
+impl ConfigChange {
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Add { target, .. }
+            | Self::Rename { target, .. }
+            | Self::Hide { target, .. }
+            | Self::Reorder { target, .. }
+            | Self::Delete { target, .. } => target,
+        }
+    }
+
+    /// Makes the change to the config, which is left as it was when the change doesn't fit
+    pub fn apply(&self, config: &mut Value) -> Result<(), String> {
+        if let Self::Add { key, .. } = self {
+            check_key(key)?;
+        }
+        let mut changed = config.clone();
+        match self.target() {
+            QUESTIONS => self.apply_question(&mut changed)?,
+            target if aliases::column(target).is_some() => {
+                self.apply_enumeration(&mut changed[target])?
+            }
+            target => return Err(format!("unknown target '{target}'")),
+        }
+        *config = changed;
+        Ok(())
+    }
+
+    fn apply_enumeration(&self, enumeration: &mut Value) -> Result<(), String> {
+        let values = enumeration["values"]
+            .as_object_mut()
+            .ok_or("the enumeration has no values")?;
+        match self {
+            Self::Add { key, value, .. } => {
+                if values.contains_key(key) {
+                    return Err(format!("'{key}' already exists"));
+                }
+                values.insert(key.clone(), value.clone());
+                order_of(&mut enumeration["order"])?.push(key.as_str().into());
+            }
+            Self::Rename { key, name, .. } => {
+                existing(values, key)?["name"] = name.as_str().into();
+            }
+            Self::Hide { key, hidden, .. } => {
+                existing(values, key)?["hidden"] = (*hidden).into();
+            }
+            Self::Reorder { section, order, .. } => {
+                if section.is_some() {
+                    return Err("only questions are ordered by section".into());
+                }
+                reorder(order_of(&mut enumeration["order"])?, order)?;
+            }
+            Self::Delete { key, .. } => {
+                values
+                    .remove(key)
+                    .ok_or_else(|| format!("'{key}' doesn't exist"))?;
+                order_of(&mut enumeration["order"])?.retain(|k| k != key.as_str());
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_question(&self, config: &mut Value) -> Result<(), String> {
+        let qc_checks = &mut config["qc_checks"];
+        let questions = qc_checks["questions"]
+            .as_object_mut()
+            .ok_or("the checklist has no questions")?;
+        match self {
+            Self::Add {
+                key,
+                value,
+                section,
+                ..
+            } => {
+                if questions.contains_key(key) {
+                    return Err(format!("'{key}' already exists"));
+                }
+                questions.insert(key.clone(), value.clone());
+                let section = section
+                    .as_deref()
+                    .ok_or("questions are added to a section")?;
+                section_of(&mut qc_checks["tech_form"], section)?.push(key.as_str().into());
+                if let Ok(pdf_section) = section_of(&mut qc_checks["pdf_form"], section) {
+                    pdf_section.push(key.as_str().into());
+                }
+            }
+            Self::Rename { key, name, .. } => {
+                existing(questions, key)?["question"] = name.as_str().into();
+            }
+            Self::Hide { key, hidden, .. } => {
+                existing(questions, key)?["hidden"] = (*hidden).into();
+            }
+            Self::Reorder { section, order, .. } => {
+                let section = section
+                    .as_deref()
+                    .ok_or("questions are ordered by section")?;
+                reorder(section_of(&mut qc_checks["tech_form"], section)?, order)?;
+                // the printable can list fewer questions, those it has follow the same order
+                if let Ok(pdf_section) = section_of(&mut qc_checks["pdf_form"], section) {
+                    pdf_section.sort_by_key(|key| {
+                        order
+                            .iter()
+                            .position(|k| key == k.as_str())
+                            .unwrap_or(order.len())
+                    });
+                }
+            }
+            Self::Delete { key, .. } => {
+                questions
+                    .remove(key)
+                    .ok_or_else(|| format!("'{key}' doesn't exist"))?;
+                for form in ["tech_form", "pdf_form"] {
+                    for section in qc_checks[form].as_array_mut().into_iter().flatten() {
+                        if let Some(listed) = section["questions"].as_array_mut() {
+                            listed.retain(|k| k != key.as_str());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// New keys end up in stored forms and in search queries, so they are kept to
+/// lowercase letters, digits and underscores
+fn check_key(key: &str) -> Result<(), String> {
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        return Err(format!(
+            "'{key}' can only use lowercase letters, digits and underscores"
+        ));
+    }
+    Ok(())
+}
+
+fn existing<'a>(
+    values: &'a mut serde_json::Map<String, Value>,
+    key: &str,
+) -> Result<&'a mut Value, String> {
+    values
+        .get_mut(key)
+        .ok_or_else(|| format!("'{key}' doesn't exist"))
+}
+
+fn order_of(order: &mut Value) -> Result<&mut Vec<Value>, String> {
+    order
+        .as_array_mut()
+        .ok_or_else(|| "the enumeration has no order".into())
+}
+
+fn section_of<'a>(form: &'a mut Value, heading: &str) -> Result<&'a mut Vec<Value>, String> {
+    form.as_array_mut()
+        .into_iter()
+        .flatten()
+        .find(|section| section["heading"] == heading)
+        .and_then(|section| section["questions"].as_array_mut())
+        .ok_or_else(|| format!("there is no section '{heading}'"))
+}
+
+/// Replaces `current` with `order` as long as it holds the same keys
+fn reorder(current: &mut Vec<Value>, order: &[String]) -> Result<(), String> {
+    let mut before: Vec<&str> = current.iter().filter_map(Value::as_str).collect();
+    let mut after: Vec<&str> = order.iter().map(String::as_str).collect();
+    before.sort_unstable();
+    after.sort_unstable();
+    if before != after {
+        return Err("the new order has to list the same keys".into());
+    }
+    *current = order.iter().map(|key| key.as_str().into()).collect();
+    Ok(())
+}
+
+/// How many forms would be left pointing at a deleted value or question
+fn forms_using(conn: &mut diesel::SqliteConnection, target: &str, key: &str) -> Result<i64> {
+    let query = qc_forms::table.count();
+    let count = if target == QUESTIONS {
+        query
+            .filter(
+                // answers are stored as `key:answers,` one after the other
+                sql::<Bool>("instr(',' || qc_answers, ")
+                    .bind::<Text, _>(format!(",{key}:"))
+                    .sql(") > 0"),
+            )
+            .get_result(conn)?
+    } else {
+        let Some(column) = aliases::column(target) else {
+            return Ok(0);
+        };
+        // `column` comes from a fixed list so it is safe to put into the query
+        query
+            .filter(sql::<Bool>(&format!("{column} = ")).bind::<Text, _>(key))
+            .get_result(conn)?
+    };
+    Ok(count)
+}
+
+/// The config file with the stored changes made on top of it. Changes that no longer
+/// fit the file, like renaming a value that was removed from it, are skipped
+pub fn effective(conn: &mut diesel::SqliteConnection, config: &Config) -> Result<Config> {
+    let changes: Vec<StoredConfigChange> = config_changes::table
+        .order(config_changes::id.asc())
+        .load(conn)?;
+
+    let mut items = config.0.clone();
+    for stored in changes {
+        let mut changed = items.clone();
+        let applied = serde_json::from_value::<ConfigChange>(stored.change.0)
+            .map_err(|err| err.to_string())
+            .and_then(|change| change.apply(&mut changed))
+            .and_then(|_| {
+                let changed = Config::new(changed);
+                changed.model().map_err(|problems| problems.to_string())?;
+                Ok(changed.0)
+            });
+        match applied {
+            Ok(changed) => items = changed,
+            Err(err) => rocket::warn!("Skipping config change {}: {err}", stored.id),
+        }
+    }
+    Ok(Config::new(items))
+}
+
+/// Stores `change` once it fits the config and leaves it valid
+fn record(
+    conn: &mut diesel::SqliteConnection,
+    config: Config,
+    change: &ConfigChange,
+) -> Result<StoredConfigChange> {
+    conn.transaction(|conn| {
+        let mut items = effective(conn, &config)?.0;
+        change
+            .apply(&mut items)
+            .map_err(DataBaseError::InvalidConfigChange)?;
+        Config::new(items)
+            .model()
+            .map_err(|problems| DataBaseError::InvalidConfig(problems.0))?;
+
+        if let ConfigChange::Delete { target, key } = change {
+            let forms = forms_using(conn, target, key)?;
+            if forms > 0 {
+                return Err(DataBaseError::ConfigValueInUse {
+                    key: key.clone(),
+                    forms,
+                });
+            }
+        }
+
+        diesel::insert_into(config_changes::table)
+            .values(NewConfigChange {
+                change: serde_json::to_string(change).expect("changes serialize"),
+                creation_date: time_default(),
+            })
+            .execute(conn)?;
+        Ok(config_changes::table
+            .order(config_changes::id.desc())
+            .first(conn)?)
+    })
+}
+
+/// Every change made so far, oldest first
+#[get("/config_changes")]
+pub(super) async fn list_config_changes(
+    db: Db,
+    _admin: Admin,
+) -> Result<Json<Vec<StoredConfigChange>>> {
+    db.run(move |conn| {
+        Ok(Json(
+            config_changes::table
+                .order(config_changes::id.asc())
+                .load(conn)?,
+        ))
+    })
+    .await
+}
+
+#[post("/config_changes", data = "<change>")]
+pub(super) async fn new_config_change(
+    db: Db,
+    live: &State<LiveConfig>,
+    current: &State<CurrentChecklist>,
+    change: Json<ConfigChange>,
+    _admin: Admin,
+) -> Result<Created<Json<StoredConfigChange>>> {
+    let config = live
+        .read()
+        .map_err(|problems| DataBaseError::InvalidConfig(problems.0))?;
+    let stored = db.run(move |conn| record(conn, config, &change)).await?;
+
+    // puts the change into use the same way an edit of the file is
+    checklist::reload(&db, live, current).await?;
+    Ok(Created::new("/").body(Json(stored)))
+}
+
+#[test]
+fn test_apply_changes() {
+    let config = Config::load_from_file("./config.json5").expect("Failed to load config file");
+    let change = |json: Value| serde_json::from_value::<ConfigChange>(json).unwrap();
+    let mut items = config.0.clone();
+
+    change(serde_json::json!({
+        "action": "add", "target": "processor_gens", "key": "g015", "value": {"name": "15th Gen"}
+    }))
+    .apply(&mut items)
+    .unwrap();
+    change(serde_json::json!({"action": "hide", "target": "ram_sizes", "key": "GiB008"}))
+        .apply(&mut items)
+        .unwrap();
+    change(serde_json::json!({
+        "action": "reorder", "target": "build_types", "order": ["tablet", "laptop", "desktop"]
+    }))
+    .apply(&mut items)
+    .unwrap();
+    change(serde_json::json!({
+        "action": "add", "target": "questions", "key": "bios_updated", "section": "Final Checks",
+        "value": {"question": "BIOS updated"}
+    }))
+    .apply(&mut items)
+    .unwrap();
+    change(serde_json::json!({"action": "delete", "target": "questions", "key": "cd_drive"}))
+        .apply(&mut items)
+        .unwrap();
+
+    assert_eq!(items["processor_gens"]["order"][14], "g015");
+    assert_eq!(items["ram_sizes"]["values"]["GiB008"]["hidden"], true);
+    assert_eq!(items["build_types"]["order"][0], "tablet");
+    assert_eq!(
+        items["qc_checks"]["tech_form"][3]["questions"][2],
+        "bios_updated"
+    );
+    assert!(items["qc_checks"]["questions"].get("cd_drive").is_none());
+    Config::new(items.clone())
+        .model()
+        .expect("changed config should be valid");
+
+    // changes that don't fit leave the config alone
+    let before = items.clone();
+    for bad in [
+        serde_json::json!({"action": "rename", "target": "drive_types", "key": "tape", "name": "Tape"}),
+        serde_json::json!({"action": "reorder", "target": "build_types", "order": ["desktop"]}),
+        serde_json::json!({"action": "add", "target": "questions", "key": "x", "value": {}}),
+        serde_json::json!({"action": "hide", "target": "sales_order", "key": "x"}),
+        serde_json::json!({
+            "action": "add", "target": "ram_sizes", "key": "GiB256", "value": {"name": "256 GiB"}
+        }),
+        serde_json::json!({
+            "action": "add", "target": "questions", "key": "bios updated", "section": "Final Checks",
+            "value": {"question": "BIOS updated"}
+        }),
+    ] {
+        assert!(change(bad).apply(&mut items).is_err());
+    }
+    assert_eq!(items, before);
+
+    // the printable follows the questions being reordered
+    let mut items = config.0.clone();
+    items["qc_checks"]["pdf_form"] = serde_json::json!([
+        {"heading": "Final Checks", "questions": ["all_media_removed", "case_assembled"]}
+    ]);
+    change(serde_json::json!({
+        "action": "reorder", "target": "questions", "section": "Final Checks",
+        "order": ["case_assembled", "all_media_removed"]
+    }))
+    .apply(&mut items)
+    .unwrap();
+    for form in ["tech_form", "pdf_form"] {
+        let section = items["qc_checks"][form]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|section| section["heading"] == "Final Checks")
+            .unwrap();
+        assert_eq!(
+            section["questions"],
+            serde_json::json!(["case_assembled", "all_media_removed"])
+        );
+    }
+}
@@ -2,15 +2,18 @@ use crate::json_text::JsonText;
 
 use rocket::response::status::Created;
 use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::State;
 
 use rocket_sync_db_pools::diesel;
 
-use crate::qc_checklist::QCChecklist;
+use crate::qc_checklist::{self, QCAnswerDetails, QCChecklist};
 
 use crate::time::Time;
+use crate::Config;
 
 use self::diesel::prelude::*;
 
+use super::checklist::CurrentChecklist;
 use super::*;
 
 #[derive(Debug, Clone, Deserialize, Serialize, Queryable, Insertable, AsChangeset)]
@@ -53,14 +56,37 @@ pub struct NewQCForm {
     pub tech_notes: String,
 
     pub metadata: Option<JsonText>,
+    // notes and who answered, `qc_answers` decides the answers themselves
+    #[serde(default)]
+    pub qc_answer_details: QCAnswerDetails,
+    // always the current checklist, see `checklist::CurrentChecklist`
+    #[serde(skip_deserializing)]
+    #[serde(default)]
+    pub checklist_version: Option<i32>,
 }
 
 #[post("/new_post", data = "<post>")]
 pub(super) async fn new_post(
     db: Db,
-    post: Json<NewQCForm>,
-) -> Result<Created<Json<ExistingQCForm>>> {
-    let post: Json<ExistingQCForm> = db
+    config: &Config,
+    checklist: &State<CurrentChecklist>,
+    mut post: Json<NewQCForm>,
+) -> Result<Created<Json<SummarizedQCForm>>> {
+    // questions and patterns can differ between build locations
+    let config = config.for_location(&post.build_location);
+    check_patterns(config, &serde_json::to_value(&*post).unwrap_or_default())?;
+    post.checklist_version = Some(checklist.get(&post.build_location));
+    post.qc_answers.check_passes(config, &post.build_type)?;
+    let form = &mut *post;
+    form.qc_answers
+        .grade(config, &form.qc_answer_details, None)?;
+    form.qc_answer_details.sync(
+        None,
+        &form.qc_answers,
+        [Some(form.qc1_initial.as_str()), form.qc2_initial.as_deref()],
+        time_default(),
+    );
+    let post: ExistingQCForm = db
         .run(move |conn| {
             let count: i64 = qc_forms::table
                 .filter(qc_forms::asm_serial.eq(&post.asm_serial))
@@ -85,14 +111,50 @@ pub(super) async fn new_post(
                 return Err(DataBaseError::ExistingOemSerial);
             }
 
+            // the same model is always saved under its catalog name
+            post.make_model = device_model::normalize(conn, &post.make_model)?;
+
             diesel::insert_into(qc_forms::table)
                 .values(&*post)
                 .execute(conn)?;
 
             let res: ExistingQCForm = qc_forms::table.order(qc_forms::id.desc()).first(conn)?;
+            sales_order::refresh(conn, res.sales_order.as_deref())?;
 
-            Result::<Json<ExistingQCForm>>::Ok(res.into())
+            Result::<ExistingQCForm>::Ok(res)
         })
         .await?;
-    Ok(Created::new("/").body(post))
+    // new forms are pinned to the checklist in the config
+    let post = SummarizedQCForm::new(config, post)?;
+    Ok(Created::new("/").body(Json(post)))
+}
+
+/// The questions a form with the given fields is asked, drafts can leave fields out
+#[post("/applicable_questions", data = "<form>")]
+pub(super) async fn applicable_questions(
+    config: &Config,
+    form: Json<serde_json::Value>,
+) -> Result<Json<Vec<String>>> {
+    let location = form["build_location"].as_str().unwrap_or_default();
+    let config = config.for_location(location);
+    Ok(Json(qc_checklist::applicable_questions(config, &form)?))
+}
+
+/// Checks the fields `form` has against their patterns the same way the browser does
+pub(super) fn check_patterns(config: &Config, form: &serde_json::Value) -> Result<()> {
+    let Some(form) = form.as_object() else {
+        return Ok(());
+    };
+    for (field, value) in form {
+        let (Some(value), Some(pattern)) = (value.as_str(), config.pattern(field)) else {
+            continue;
+        };
+        if !pattern.matches(value) {
+            return Err(DataBaseError::InvalidFieldValue {
+                field: field.clone(),
+                pattern: pattern.pattern.clone(),
+            });
+        }
+    }
+    Ok(())
 }
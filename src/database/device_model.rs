@@ -0,0 +1,548 @@
+use crate::admin_pwd::Admin;
+use crate::Config;
+
+use std::collections::{BTreeMap, HashSet};
+
+use rocket::response::status::Created;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+
+use rocket_sync_db_pools::diesel;
+
+use crate::time::Time;
+
+use self::diesel::prelude::*;
+
+use super::search::aliases;
+use super::*;
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+#[serde(crate = "rocket::serde")]
+pub struct DeviceModel {
+    pub id: i32,
+    // example Dell
+    pub manufacturer: String,
+    // example OptiPlex 7050
+    pub model: String,
+    #[serde(skip)]
+    pub match_key: String,
+    // free text, example SFF
+    pub form_factor: Option<String>,
+    // the rest are keys of their enumeration, filled in on new forms when picked
+    pub build_type: Option<String>,
+    pub drive_type: Option<String>,
+    pub drive_size: Option<String>,
+    pub ram_type: Option<String>,
+    pub ram_size: Option<String>,
+    pub creation_date: Time,
+}
+
+impl DeviceModel {
+    /// What `make_model` is set to for forms of this model
+    pub fn name(&self) -> String {
+        format!("{} {}", self.manufacturer.trim(), self.model.trim())
+    }
+}
+
+/// A catalog entry as the api hands it out, with the name forms use for it
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NamedDeviceModel {
+    pub name: String,
+    #[serde(flatten)]
+    pub device_model: DeviceModel,
+}
+
+impl From<DeviceModel> for NamedDeviceModel {
+    fn from(device_model: DeviceModel) -> Self {
+        Self {
+            name: device_model.name(),
+            device_model,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name = super::schema::device_models)]
+pub struct NewDeviceModel {
+    pub manufacturer: String,
+    pub model: String,
+    #[serde(skip_deserializing)]
+    pub match_key: String,
+    #[serde(default)]
+    pub form_factor: Option<String>,
+    #[serde(default)]
+    pub build_type: Option<String>,
+    #[serde(default)]
+    pub drive_type: Option<String>,
+    #[serde(default)]
+    pub drive_size: Option<String>,
+    #[serde(default)]
+    pub ram_type: Option<String>,
+    #[serde(default)]
+    pub ram_size: Option<String>,
+    #[serde(skip_deserializing)]
+    #[serde(default = "time_default")]
+    pub creation_date: Time,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, AsChangeset)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name = super::schema::device_models)]
+#[serde(default)]
+pub struct DeviceModelUpdate {
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    #[serde(skip_deserializing)]
+    pub match_key: Option<String>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub form_factor: Option<Option<String>>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_type: Option<Option<String>>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drive_type: Option<Option<String>>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drive_size: Option<Option<String>>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ram_type: Option<Option<String>>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ram_size: Option<Option<String>>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = super::schema::device_model_spellings)]
+struct NewSpelling {
+    match_key: String,
+    device_model: i32,
+}
+
+/// The free text spellings of one model, told apart only by case, spaces and punctuation
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Cluster {
+    pub match_key: String,
+    // how many forms use each spelling
+    pub spellings: BTreeMap<String, i64>,
+    pub forms: i64,
+    // the catalog entry the spellings already resolve to
+    pub device_model: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Merge {
+    pub spellings: Vec<String>,
+    // finalized forms are left as they are unless asked for
+    #[serde(default)]
+    pub include_finalized: bool,
+}
+
+/// `make_model` values are compared by this, so 'Dell OptiPlex-7050' and
+/// 'dell optiplex 7050' are the same model
+pub fn match_key(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The defaults are only useful if a form can take them, so they have to be keys of the
+/// enumeration of their column
+fn check_defaults(config: &Config, defaults: &[(&str, Option<&str>)]) -> Result<()> {
+    if let Some(bad) = defaults.iter().find(|(column, key)| {
+        key.is_some_and(|key| {
+            let enumeration = aliases::enumeration(column).unwrap_or_default();
+            config
+                .enumeration(enumeration)
+                .is_none_or(|enumeration| !enumeration.values.contains_key(key))
+        })
+    }) {
+        return Err(DataBaseError::InvalidDeviceModel(format!(
+            "'{}' isn't a {}",
+            bad.1.unwrap_or_default(),
+            bad.0
+        )));
+    }
+    Ok(())
+}
+
+fn check_name(match_key: &str) -> Result<()> {
+    if match_key.is_empty() {
+        return Err(DataBaseError::InvalidDeviceModel(
+            "the manufacturer and model can't both be empty".into(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_unique(conn: &mut diesel::SqliteConnection, match_key: &str, id: i32) -> Result<()> {
+    let count: i64 = device_models::table
+        .filter(device_models::match_key.eq(match_key))
+        .filter(device_models::id.ne(id))
+        .count()
+        .get_result(conn)?;
+    if count > 0 {
+        return Err(DataBaseError::ExistingDeviceModel);
+    }
+    Ok(())
+}
+
+/// The catalog entry `make_model` is a spelling of, the name itself wins over a spelling
+/// merged into another entry
+pub fn find(conn: &mut diesel::SqliteConnection, make_model: &str) -> Result<Option<DeviceModel>> {
+    let key = match_key(make_model);
+    if key.is_empty() {
+        return Ok(None);
+    }
+    let found = device_models::table
+        .filter(device_models::match_key.eq(&key))
+        .first(conn)
+        .optional()?;
+    if found.is_some() {
+        return Ok(found);
+    }
+    let Some(id) = device_model_spellings::table
+        .find(&key)
+        .select(device_model_spellings::device_model)
+        .first::<i32>(conn)
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    Ok(device_models::table.find(id).first(conn).optional()?)
+}
+
+/// The name of the catalog entry `make_model` is a spelling of, models that aren't in
+/// the catalog are kept as they were typed
+pub(super) fn normalize(conn: &mut diesel::SqliteConnection, make_model: &str) -> Result<String> {
+    Ok(find(conn, make_model)?
+        .map(|found| found.name())
+        .unwrap_or_else(|| make_model.to_owned()))
+}
+
+/// Every `make_model` in use grouped by its `match_key`, leaving out the ones that
+/// already all use the name of their catalog entry
+fn clusters(conn: &mut diesel::SqliteConnection) -> Result<Vec<Cluster>> {
+    let in_use: Vec<(String, i64)> = qc_forms::table
+        .group_by(qc_forms::make_model)
+        .select((qc_forms::make_model, diesel::dsl::count_star()))
+        .load(conn)?;
+
+    let mut clusters: BTreeMap<String, Cluster> = BTreeMap::new();
+    for (make_model, forms) in in_use {
+        let key = match_key(&make_model);
+        let cluster = clusters.entry(key.clone()).or_insert_with(|| Cluster {
+            match_key: key,
+            spellings: BTreeMap::new(),
+            forms: 0,
+            device_model: None,
+        });
+        cluster.spellings.insert(make_model, forms);
+        cluster.forms += forms;
+    }
+
+    let mut res = Vec::new();
+    for (_, mut cluster) in clusters {
+        if let Some(found) = find(conn, &cluster.match_key)? {
+            let name = found.name();
+            if cluster.spellings.keys().all(|spelling| *spelling == name) {
+                continue;
+            }
+            cluster.device_model = Some(found.id);
+        }
+        res.push(cluster);
+    }
+    res.sort_by_key(|cluster| std::cmp::Reverse(cluster.forms));
+    Ok(res)
+}
+
+/// Makes `spellings` resolve to the catalog entry `id` from now on and renames the forms
+/// using them. Finalized forms are only renamed with `include_finalized`, which is fine
+/// to do since nothing but the spelling changes. Returns how many forms were renamed
+fn merge(
+    conn: &mut diesel::SqliteConnection,
+    id: i32,
+    spellings: &[String],
+    include_finalized: bool,
+) -> Result<usize> {
+    conn.transaction(|conn| {
+        let device_model: DeviceModel = device_models::table.find(id).first(conn)?;
+        let mut keys: HashSet<String> = spellings.iter().map(|s| match_key(s)).collect();
+        keys.remove("");
+        for key in &keys {
+            if *key == device_model.match_key {
+                continue;
+            }
+            diesel::replace_into(device_model_spellings::table)
+                .values(NewSpelling {
+                    match_key: key.clone(),
+                    device_model: id,
+                })
+                .execute(conn)?;
+        }
+        keys.insert(device_model.match_key.clone());
+
+        let in_use: Vec<String> = qc_forms::table
+            .select(qc_forms::make_model)
+            .distinct()
+            .load(conn)?;
+        let name = device_model.name();
+        let renamed: Vec<String> = in_use
+            .into_iter()
+            .filter(|make_model| *make_model != name && keys.contains(&match_key(make_model)))
+            .collect();
+        let mut forms = qc_forms::table
+            .filter(qc_forms::make_model.eq_any(renamed))
+            .into_boxed();
+        if !include_finalized {
+            forms = forms.filter(qc_forms::finalized.eq(false));
+        }
+        let ids: Vec<i32> = forms.select(qc_forms::id).load(conn)?;
+        Ok(
+            diesel::update(qc_forms::table.filter(qc_forms::id.eq_any(ids)))
+                .set((
+                    qc_forms::make_model.eq(name),
+                    qc_forms::last_updated.eq(time_default()),
+                ))
+                .execute(conn)?,
+        )
+    })
+}
+
+/// The catalog entries matching what was typed so far, for autocompleting `make_model`
+#[get("/device_models?<search>&<limit>")]
+pub(super) async fn list_device_models(
+    db: Db,
+    search: Option<String>,
+    limit: Option<i64>,
+) -> Result<Json<Vec<NamedDeviceModel>>> {
+    let key = match_key(search.as_deref().unwrap_or_default());
+    db.run(move |conn| {
+        let found: Vec<DeviceModel> = device_models::table
+            .filter(device_models::match_key.like(format!("%{key}%")))
+            .order((
+                device_models::manufacturer.asc(),
+                device_models::model.asc(),
+            ))
+            .limit(limit.unwrap_or(20).clamp(1, 100))
+            .load(conn)?;
+        Ok(Json(found.into_iter().map(Into::into).collect()))
+    })
+    .await
+}
+
+#[post("/device_models", data = "<device_model>")]
+pub(super) async fn new_device_model(
+    db: Db,
+    config: &Config,
+    mut device_model: Json<NewDeviceModel>,
+    _admin: Admin,
+) -> Result<Created<Json<NamedDeviceModel>>> {
+    device_model.match_key = match_key(&format!(
+        "{}{}",
+        device_model.manufacturer, device_model.model
+    ));
+    check_name(&device_model.match_key)?;
+    check_defaults(
+        config,
+        &[
+            ("build_type", device_model.build_type.as_deref()),
+            ("drive_type", device_model.drive_type.as_deref()),
+            ("drive_size", device_model.drive_size.as_deref()),
+            ("ram_type", device_model.ram_type.as_deref()),
+            ("ram_size", device_model.ram_size.as_deref()),
+        ],
+    )?;
+
+    let created: DeviceModel = db
+        .run(move |conn| {
+            check_unique(conn, &device_model.match_key, 0)?;
+            diesel::insert_into(device_models::table)
+                .values(&*device_model)
+                .execute(conn)?;
+            Result::<DeviceModel>::Ok(
+                device_models::table
+                    .order(device_models::id.desc())
+                    .first(conn)?,
+            )
+        })
+        .await?;
+    Ok(Created::new("/").body(Json(created.into())))
+}
+
+#[post("/device_models/<id>", data = "<update>")]
+pub(super) async fn update_device_model(
+    db: Db,
+    config: &Config,
+    id: i32,
+    mut update: Json<DeviceModelUpdate>,
+    _admin: Admin,
+) -> Result<Json<NamedDeviceModel>> {
+    check_defaults(
+        config,
+        &[
+            ("build_type", update.build_type.clone().flatten().as_deref()),
+            ("drive_type", update.drive_type.clone().flatten().as_deref()),
+            ("drive_size", update.drive_size.clone().flatten().as_deref()),
+            ("ram_type", update.ram_type.clone().flatten().as_deref()),
+            ("ram_size", update.ram_size.clone().flatten().as_deref()),
+        ],
+    )?;
+    db.run(move |conn| {
+        conn.transaction(|conn| {
+            let existing: DeviceModel = device_models::table.find(id).first(conn)?;
+            let key = match_key(&format!(
+                "{}{}",
+                update
+                    .manufacturer
+                    .as_ref()
+                    .unwrap_or(&existing.manufacturer),
+                update.model.as_ref().unwrap_or(&existing.model)
+            ));
+            check_name(&key)?;
+            check_unique(conn, &key, id)?;
+            if key != existing.match_key {
+                // forms still using the old name keep resolving to this entry
+                diesel::replace_into(device_model_spellings::table)
+                    .values(NewSpelling {
+                        match_key: existing.match_key.clone(),
+                        device_model: id,
+                    })
+                    .execute(conn)?;
+            }
+            update.match_key = Some(key);
+
+            diesel::update(device_models::table.find(id))
+                .set(&*update)
+                .execute(conn)?;
+            let updated: DeviceModel = device_models::table.find(id).first(conn)?;
+            Ok(Json(updated.into()))
+        })
+    })
+    .await
+}
+
+/// Forms keep the name they were saved with, it just isn't normalized anymore
+#[delete("/device_models/<id>")]
+pub(super) async fn delete_device_model(db: Db, id: i32, _admin: Admin) -> Result<()> {
+    db.run(move |conn| {
+        conn.transaction(|conn| {
+            diesel::delete(
+                device_model_spellings::table.filter(device_model_spellings::device_model.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(device_models::table.find(id)).execute(conn)?;
+            Ok(())
+        })
+    })
+    .await
+}
+
+/// The free text `make_model` values that still need merging into the catalog, the
+/// spellings used by the most forms first
+#[get("/device_models/clusters")]
+pub(super) async fn list_clusters(db: Db, _admin: Admin) -> Result<Json<Vec<Cluster>>> {
+    db.run(move |conn| Ok(Json(clusters(conn)?))).await
+}
+
+#[post("/device_models/<id>/merge", data = "<merge>")]
+pub(super) async fn merge_spellings(
+    db: Db,
+    id: i32,
+    merge: Json<Merge>,
+    _admin: Admin,
+) -> Result<Json<serde_json::Value>> {
+    let forms = db
+        .run(move |conn| self::merge(conn, id, &merge.spellings, merge.include_finalized))
+        .await?;
+    Ok(Json(serde_json::json!({ "forms": forms })))
+}
+
+#[test]
+fn test_match_key() {
+    assert_eq!(match_key("Dell OptiPlex-7050"), "delloptiplex7050");
+    assert_eq!(match_key(" dell  optiplex 7050 "), "delloptiplex7050");
+    assert_eq!(
+        match_key("HP EliteBook 840 G5"),
+        match_key("hp elitebook 840g5")
+    );
+    assert_ne!(match_key("Lenovo T480"), match_key("Lenovo T480s"));
+    assert_eq!(match_key(" - "), "");
+}
+
+#[test]
+fn test_find_and_merge() {
+    use diesel_migrations::MigrationHarness;
+
+    let conn = &mut diesel::SqliteConnection::establish(":memory:").unwrap();
+    conn.run_pending_migrations(MIGRATIONS).unwrap();
+
+    let mut optiplex: NewDeviceModel = serde_json::from_value(
+        serde_json::json!({"manufacturer": "Dell", "model": "OptiPlex 7050"}),
+    )
+    .unwrap();
+    optiplex.match_key = match_key("DellOptiPlex 7050");
+    diesel::insert_into(device_models::table)
+        .values(&optiplex)
+        .execute(conn)
+        .unwrap();
+    let id: i32 = device_models::table
+        .select(device_models::id)
+        .first(conn)
+        .unwrap();
+
+    assert_eq!(
+        normalize(conn, "dell optiplex-7050").unwrap(),
+        "Dell OptiPlex 7050"
+    );
+    assert_eq!(normalize(conn, "Optiplex 7050").unwrap(), "Optiplex 7050");
+    assert!(find(conn, " - ").unwrap().is_none());
+
+    let form = |conn: &mut diesel::SqliteConnection, make_model: &str, finalized: bool| {
+        diesel::sql_query(
+            "INSERT INTO qc_forms (finalized, creation_date, last_updated, build_location, \
+             build_type, drive_type, item_serial, oem_serial, make_model, mso_installed, \
+             operating_system, processor_gen, processor_type, qc_answers, qc1_initial, \
+             ram_size, ram_type, drive_size, tech_notes, qc_answer_details) VALUES (?, \
+             '2020-01-01 00:00:00+00:00', '2020-01-01 00:00:00+00:00', '', '', '', '', '', ?, \
+             FALSE, '', '', '', '{}', '', '', '', '', '', '{}')",
+        )
+        .bind::<diesel::sql_types::Bool, _>(finalized)
+        .bind::<diesel::sql_types::Text, _>(make_model)
+        .execute(conn)
+        .unwrap();
+    };
+    form(conn, "Optiplex 7050", false);
+    form(conn, "OPTIPLEX-7050", true);
+    form(conn, "Dell OptiPlex 7050", false);
+
+    // finalized forms are left alone unless asked for
+    let spellings = ["Optiplex 7050".to_owned()];
+    assert_eq!(merge(conn, id, &spellings, false).unwrap(), 1);
+    assert_eq!(
+        normalize(conn, "optiplex 7050").unwrap(),
+        "Dell OptiPlex 7050"
+    );
+    let renamed: Vec<(String, Time)> = qc_forms::table
+        .filter(qc_forms::finalized.eq(false))
+        .select((qc_forms::make_model, qc_forms::last_updated))
+        .load(conn)
+        .unwrap();
+    assert_eq!(renamed[0].0, "Dell OptiPlex 7050");
+    assert!(renamed[0].1 .0.year() > 2020);
+    assert_eq!(renamed[1].1 .0.year(), 2020);
+
+    assert_eq!(merge(conn, id, &spellings, true).unwrap(), 1);
+    let names: Vec<String> = qc_forms::table
+        .select(qc_forms::make_model)
+        .distinct()
+        .load(conn)
+        .unwrap();
+    assert_eq!(names, ["Dell OptiPlex 7050"]);
+}
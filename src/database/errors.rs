@@ -24,6 +24,44 @@ pub enum DataBaseError {
     DataBaseSearchError(#[from] ExpressionParserError<VisitorError>),
     #[error("Invalid column specified '{0:?}'")]
     InvalidColumn(String),
+    #[error("A saved search with the provided name already exists for this owner")]
+    ExistingSavedSearch,
+    #[error("A saved search without an owner must be shared")]
+    SavedSearchWithoutOwner,
+    #[error("Only the owner of a saved search can change it")]
+    NotSavedSearchOwner,
+    #[error(
+        "'{question}' has {found} answers but should have one for each of its {expected} passes"
+    )]
+    InvalidPassCount {
+        question: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("'{0}' was answered without a value")]
+    MissingAnswerValue(String),
+    #[error("Invalid value for '{question}': {reason}")]
+    InvalidAnswerValue { question: String, reason: String },
+    #[error("'{field}' doesn't match the pattern '{pattern}'")]
+    InvalidFieldValue { field: String, pattern: String },
+    #[error("The config is invalid: {}", .0.join(", "))]
+    InvalidConfig(Vec<String>),
+    #[error("The config change can't be made: {0}")]
+    InvalidConfigChange(String),
+    #[error("'{key}' can't be deleted while {forms} forms use it")]
+    ConfigValueInUse { key: String, forms: i64 },
+    #[error("The device model is invalid: {0}")]
+    InvalidDeviceModel(String),
+    #[error("A device model with the same manufacturer and model already exists")]
+    ExistingDeviceModel,
+    #[error("The sales order is invalid: {0}")]
+    InvalidSalesOrder(String),
+    #[error("A sales order with the provided number already exists")]
+    ExistingSalesOrder,
+    #[error("Only finalized forms can have their unit tracked")]
+    UnitNotFinalized,
+    #[error("The event can't be recorded: {0}")]
+    InvalidUnitEvent(String),
 }
 fn nothing<T: std::fmt::Debug, S>(t: &T, s: S) -> Result<S::Ok, S::Error>
 where
@@ -43,7 +81,7 @@ impl<'r> Responder<'r, 'static> for DataBaseError {
         Response::build()
             .header(rocket::http::ContentType::Plain)
             .status(rocket::http::Status::BadRequest)
-            .streamed_body(Cursor::new(serde_json::to_vec(&self).unwrap_or(Vec::new())))
+            .streamed_body(Cursor::new(serde_json::to_vec(&self).unwrap_or_default()))
             .ok()
     }
 }
@@ -7,7 +7,8 @@ use rocket::{Build, Rocket};
 
 use rocket_sync_db_pools::diesel;
 
-use crate::qc_checklist::QCChecklist;
+use crate::qc_checklist::{ChecklistSummary, QCAnswerDetails, QCChecklist};
+use crate::{Config, LiveConfig};
 
 use crate::time::Time;
 
@@ -17,10 +18,16 @@ pub use self::errors::*;
 use self::schema::*;
 
 pub mod admin;
+pub mod checklist;
+pub mod config_change;
 pub mod create;
+pub mod device_model;
 pub mod errors;
+pub mod sales_order;
+pub mod saved_search;
 pub mod schema;
 pub mod search;
+pub mod unit_event;
 pub mod update;
 
 #[database("diesel")]
@@ -33,34 +40,77 @@ impl Db {
             .await?;
         Ok(form)
     }
+
+    /// See `finalize_post`
+    pub async fn finalize_form(&self, id: i32) -> Result<ExistingQCForm> {
+        self.run(move |conn| update::finalize(conn, id)).await
+    }
 }
 
 pub fn stage() -> AdHoc {
     AdHoc::on_ignite("Databse", |rocket| async {
+        // the routes and checklist versions need the config, which the app loads before
+        // this stage but a rocket with only this stage attached doesn't
+        let rocket = match rocket.state::<LiveConfig>() {
+            Some(_) => rocket,
+            None => rocket.attach(crate::config::stage()),
+        };
         rocket
             .attach(Db::fairing())
             .attach(AdHoc::on_ignite("Diesel Migrations", run_migrations))
+            .attach(AdHoc::try_on_ignite(
+                "Checklist Versions",
+                checklist::register_current,
+            ))
+            .attach(checklist::watch_config())
             .mount(
                 "/api",
                 routes![
                     search::get_post,
                     create::new_post,
+                    create::applicable_questions,
                     update::update_post,
                     search::search,
                     search::tokenize,
                     search::compile,
+                    search::explain::explain,
                     update::finalize_post,
                     admin::definalize_post,
-                    admin::delete_post
+                    admin::delete_post,
+                    admin::reload_config,
+                    saved_search::list_saved_searches,
+                    saved_search::get_saved_search,
+                    saved_search::new_saved_search,
+                    saved_search::update_saved_search,
+                    saved_search::delete_saved_search,
+                    saved_search::run_saved_search,
+                    checklist::list_checklists,
+                    checklist::get_checklist,
+                    config_change::list_config_changes,
+                    config_change::new_config_change,
+                    device_model::list_device_models,
+                    device_model::new_device_model,
+                    device_model::update_device_model,
+                    device_model::delete_device_model,
+                    device_model::list_clusters,
+                    device_model::merge_spellings,
+                    sales_order::list_sales_orders,
+                    sales_order::get_sales_order,
+                    sales_order::new_sales_order,
+                    sales_order::update_sales_order,
+                    sales_order::delete_sales_order,
+                    unit_event::list_unit_events,
+                    unit_event::new_unit_event
                 ],
             )
     })
 }
 
-async fn run_migrations(rocket: Rocket<Build>) -> Rocket<Build> {
-    use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+const MIGRATIONS: diesel_migrations::EmbeddedMigrations =
+    diesel_migrations::embed_migrations!("db/diesel/migrations");
 
-    const MIGRATIONS: EmbeddedMigrations = embed_migrations!("db/diesel/migrations");
+async fn run_migrations(rocket: Rocket<Build>) -> Rocket<Build> {
+    use diesel_migrations::MigrationHarness;
 
     Db::get_one(&rocket)
         .await
@@ -113,6 +163,37 @@ pub struct ExistingQCForm {
     pub tech_notes: String,
 
     pub metadata: Option<JsonText>,
+    pub qc_answer_details: QCAnswerDetails,
+    // the checklist version the questions are looked up in
+    #[serde(default)]
+    pub checklist_version: Option<i32>,
+}
+
+/// A single form as the api and templates hand it out, with its checklist summed up
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SummarizedQCForm {
+    #[serde(flatten)]
+    pub form: ExistingQCForm,
+    pub summary: ChecklistSummary,
+}
+
+impl SummarizedQCForm {
+    /// `config` has to be the one the form is pinned to, see `checklist::pinned`
+    pub fn new(config: &Config, form: ExistingQCForm) -> Result<Self> {
+        let fields = serde_json::to_value(&form).unwrap_or_default();
+        let summary = form.qc_answers.summary(config, &fields)?;
+        Ok(Self { form, summary })
+    }
+
+    pub fn load(
+        conn: &mut diesel::SqliteConnection,
+        config: &Config,
+        form: ExistingQCForm,
+    ) -> Result<Self> {
+        let config = checklist::pinned(conn, config, &form.build_location, form.checklist_version)?;
+        Self::new(&config, form)
+    }
 }
 
 pub fn time_default() -> Time {
@@ -143,8 +224,9 @@ mod tests {
     use time::OffsetDateTime;
 
     use crate::{
+        config::{Enumeration, QuestionKind},
         database::{create::NewQCForm, Time},
-        qc_checklist::{QCChecklist, QuestionAnswer, QuestionAnswers},
+        qc_checklist::{self, QCChecklist, QuestionAnswer, QuestionAnswers},
     };
 
     use super::{schema::qc_forms, Db};
@@ -160,9 +242,9 @@ mod tests {
 
     #[test]
     fn fuzz_data() {
-        let conf = crate::Config::load_from_file("./config.json5")
-            .expect("Failed to load config file. Fatial Error")
-            .0;
+        let config = crate::Config::load("./config.json5")
+            .expect("Failed to load config file. Fatial Error");
+        let model = config.model().expect("Config was validated when loaded");
 
         #[derive(Debug, Rand, Copy, Clone)]
         enum SerialStart {
@@ -222,16 +304,9 @@ mod tests {
                 format!("{:?}", rng.gen::<T>())
             }
 
-            fn random_val(rng: &mut ThreadRng, name: &str, vals: &serde_json::Value) -> String {
-                let arr = vals
-                    .get(name)
-                    .unwrap()
-                    .get("order")
-                    .unwrap()
-                    .as_array()
-                    .unwrap();
-                let index = rng.gen_range(0, arr.len());
-                arr[index].as_str().unwrap().to_owned()
+            fn random_val(rng: &mut ThreadRng, enumeration: &Enumeration) -> String {
+                let index = rng.gen_range(0, enumeration.order.len());
+                enumeration.order[index].clone()
             }
 
             fn random_question(rng: &mut ThreadRng) -> QuestionAnswer {
@@ -246,11 +321,11 @@ mod tests {
                 }
             }
 
-            let drive_size = random_val(rng, "drive_sizes", &conf);
+            let drive_size = random_val(rng, &model.drive_sizes);
             let sales_order = String::new();
-            let build_type = random_val(rng, "build_types", &conf);
+            let build_type = random_val(rng, &model.build_types);
 
-            let form = NewQCForm {
+            let mut form = NewQCForm {
                 creation_date: Time(
                     OffsetDateTime::from_unix_timestamp(rng.gen_range(
                         time::Date::MIN.midnight().assume_utc().unix_timestamp(),
@@ -265,8 +340,8 @@ mod tests {
                     ))
                     .unwrap(),
                 ),
-                build_location: random_val(rng, "build_locations", &conf),
-                drive_type: random_val(rng, "drive_types", &conf),
+                build_location: random_val(rng, &model.build_locations),
+                drive_type: random_val(rng, &model.drive_types),
                 item_serial: {
                     let kind = rng.gen::<SerialStart>();
                     let range = if rng.gen_range(0.0, 1.0) < 0.1 {
@@ -318,48 +393,43 @@ mod tests {
                 },
                 make_model: random_str::<make_model>(rng),
                 mso_installed: rng.gen::<bool>(),
-                operating_system: random_val(rng, "operating_systems", &conf),
-                processor_gen: random_val(rng, "processor_gens", &conf),
-                processor_type: random_val(rng, "processor_types", &conf),
-                qc_answers: {
-                    let mut checks = QCChecklist::new();
-
-                    for (id, check) in conf["qc_checks"]["questions"].as_object().unwrap().iter() {
-                        if check
-                            .get("whitelist_build_types")
-                            .map(|f| {
-                                f.as_array()
-                                    .map(|f| {
-                                        f.contains(&serde_json::Value::String(build_type.clone()))
-                                    })
-                                    .unwrap()
-                            })
-                            .unwrap_or(true)
-                        {
-                            checks.0.insert(
-                                id.to_owned(),
-                                QuestionAnswers([random_question(rng), random_question(rng)]),
-                            );
-                        }
-                    }
-                    checks
-                },
+                operating_system: random_val(rng, &model.operating_systems),
+                processor_gen: random_val(rng, &model.processor_gens),
+                processor_type: random_val(rng, &model.processor_types),
+                qc_answers: QCChecklist::new(),
                 qc1_initial: random_str::<Initial>(rng),
                 qc2_initial: if rng.gen::<bool>() {
                     None
                 } else {
                     Some(random_str::<Initial>(rng))
                 },
-                ram_size: random_val(rng, "ram_sizes", &conf),
-                ram_type: random_val(rng, "ram_types", &conf),
+                ram_size: random_val(rng, &model.ram_sizes),
+                ram_type: random_val(rng, &model.ram_types),
                 drive_size,
                 sales_order: None,
                 tech_notes: "".into(),
                 metadata: None,
+                qc_answer_details: Default::default(),
+                checklist_version: None,
                 build_type,
                 finalized: false,
             };
 
+            // rules can depend on any field so the answers come last
+            let draft = serde_json::to_value(&form).unwrap();
+            for (id, question) in &model.qc_checks.questions {
+                // typed questions need a value to be answered
+                if question.kind != QuestionKind::Check {
+                    continue;
+                }
+                if qc_checklist::applies(&config, id, &draft).unwrap() {
+                    form.qc_answers.0.insert(
+                        id.to_owned(),
+                        QuestionAnswers(vec![random_question(rng), random_question(rng)]),
+                    );
+                }
+            }
+
             assert_eq!(
                 client.post("/api/new_post").json(&form).dispatch().status(),
                 Status::Created
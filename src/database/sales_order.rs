@@ -0,0 +1,327 @@
+use crate::admin_pwd::Admin;
+use crate::{Config, SharedConfig};
+
+use rocket::response::status::Created;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+
+use rocket_sync_db_pools::diesel;
+
+use diesel::{
+    backend::Backend,
+    deserialize::{FromSql, FromSqlRow},
+    expression::AsExpression,
+    serialize::ToSql,
+    sql_types::Text,
+    sqlite::Sqlite,
+};
+
+use crate::time::Time;
+
+use self::diesel::prelude::*;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub enum SalesOrderStatus {
+    /// no units have been built yet
+    Open,
+    InProgress,
+    /// every expected unit is finalized
+    Complete,
+    /// only set by hand, an order stays cancelled whatever happens to its units
+    Cancelled,
+}
+
+impl SalesOrderStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::InProgress => "in_progress",
+            Self::Complete => "complete",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// The status of an order going by its units, see `refresh`
+    fn of(expected_units: i32, units: i64, finalized: i64) -> Self {
+        if finalized >= i64::from(expected_units) {
+            Self::Complete
+        } else if units > 0 {
+            Self::InProgress
+        } else {
+            Self::Open
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for SalesOrderStatus {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Sqlite>,
+    ) -> diesel::serialize::Result {
+        out.set_value(self.as_str());
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for SalesOrderStatus {
+    fn from_sql(bytes: <Sqlite as Backend>::RawValue<'_>) -> diesel::deserialize::Result<Self> {
+        let val = <String as diesel::deserialize::FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(serde_json::Value::String(val))?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+#[serde(crate = "rocket::serde")]
+pub struct SalesOrder {
+    pub id: i32,
+    // the number forms put in their `sales_order`
+    pub sales_order: String,
+    pub customer: String,
+    pub expected_units: i32,
+    pub due_date: Option<Time>,
+    pub status: SalesOrderStatus,
+    pub creation_date: Time,
+    pub last_updated: Time,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name = super::schema::sales_orders)]
+pub struct NewSalesOrder {
+    pub sales_order: String,
+    pub customer: String,
+    pub expected_units: i32,
+    #[serde(default)]
+    pub due_date: Option<Time>,
+    // worked out from the units already using the number
+    #[serde(skip_deserializing)]
+    #[serde(default = "status_default")]
+    pub status: SalesOrderStatus,
+    #[serde(skip_deserializing)]
+    #[serde(default = "time_default")]
+    pub creation_date: Time,
+    #[serde(skip_deserializing)]
+    #[serde(default = "time_default")]
+    pub last_updated: Time,
+}
+
+fn status_default() -> SalesOrderStatus {
+    SalesOrderStatus::Open
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, AsChangeset)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name = super::schema::sales_orders)]
+#[serde(default)]
+pub struct SalesOrderUpdate {
+    #[serde(skip_deserializing)]
+    pub last_updated: Option<Time>,
+    pub customer: Option<String>,
+    pub expected_units: Option<i32>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<Option<Time>>,
+    /// only cancelling is up to the user, any other status reopens a cancelled order and
+    /// is then worked out from its units
+    pub status: Option<SalesOrderStatus>,
+}
+
+/// An order with the forms built for it
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SalesOrderUnits {
+    #[serde(flatten)]
+    pub order: SalesOrder,
+    pub finalized_units: i64,
+    pub units: Vec<SummarizedQCForm>,
+}
+
+fn check_expected_units(expected_units: i32) -> Result<()> {
+    if expected_units < 1 {
+        return Err(DataBaseError::InvalidSalesOrder(
+            "an order expects at least one unit".into(),
+        ));
+    }
+    Ok(())
+}
+
+fn find(conn: &mut diesel::SqliteConnection, sales_order: &str) -> Result<SalesOrder> {
+    Ok(sales_orders::table
+        .filter(sales_orders::sales_order.eq(sales_order))
+        .first(conn)?)
+}
+
+/// Moves the order the number belongs to along as its units are built and finalized,
+/// called whenever a form using it changes. Numbers without an order are left alone
+pub(super) fn refresh(
+    conn: &mut diesel::SqliteConnection,
+    sales_order: Option<&str>,
+) -> Result<()> {
+    let Some(sales_order) = sales_order else {
+        return Ok(());
+    };
+    let Some(order) = sales_orders::table
+        .filter(sales_orders::sales_order.eq(sales_order))
+        .first::<SalesOrder>(conn)
+        .optional()?
+    else {
+        return Ok(());
+    };
+    if order.status == SalesOrderStatus::Cancelled {
+        return Ok(());
+    }
+
+    let units = qc_forms::table.filter(qc_forms::sales_order.eq(sales_order));
+    let count: i64 = units.count().get_result(conn)?;
+    let finalized: i64 = units
+        .filter(qc_forms::finalized.eq(true))
+        .count()
+        .get_result(conn)?;
+    let status = SalesOrderStatus::of(order.expected_units, count, finalized);
+    if status != order.status {
+        diesel::update(sales_orders::table.find(order.id))
+            .set((
+                sales_orders::status.eq(status),
+                sales_orders::last_updated.eq(time_default()),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+#[get("/sales_orders?<status>")]
+pub(super) async fn list_sales_orders(
+    db: Db,
+    status: Option<String>,
+) -> Result<Json<Vec<SalesOrder>>> {
+    db.run(move |conn| {
+        let mut query = sales_orders::table.into_boxed();
+        if let Some(status) = status {
+            query = query.filter(sales_orders::status.eq(status));
+        }
+        Ok(query
+            .order((
+                sales_orders::due_date.is_null(),
+                sales_orders::due_date.asc(),
+            ))
+            .load(conn)?
+            .into())
+    })
+    .await
+}
+
+#[get("/sales_orders/<sales_order>")]
+pub(super) async fn get_sales_order(
+    db: Db,
+    config: SharedConfig,
+    sales_order: String,
+) -> Result<Json<SalesOrderUnits>> {
+    db.run(move |conn| {
+        let order = find(conn, &sales_order)?;
+        let forms: Vec<ExistingQCForm> = qc_forms::table
+            .filter(qc_forms::sales_order.eq(&sales_order))
+            .order(qc_forms::id.asc())
+            .load(conn)?;
+        let finalized_units = forms.iter().filter(|form| form.finalized).count() as i64;
+        let units = forms
+            .into_iter()
+            .map(|form| SummarizedQCForm::load(conn, &config, form))
+            .collect::<Result<_>>()?;
+        Ok(Json(SalesOrderUnits {
+            order,
+            finalized_units,
+            units,
+        }))
+    })
+    .await
+}
+
+#[post("/sales_orders", data = "<order>")]
+pub(super) async fn new_sales_order(
+    db: Db,
+    config: &Config,
+    order: Json<NewSalesOrder>,
+) -> Result<Created<Json<SalesOrder>>> {
+    // the number has to be one a form could be saved with
+    create::check_patterns(
+        config,
+        &serde_json::json!({ "sales_order": order.sales_order }),
+    )?;
+    check_expected_units(order.expected_units)?;
+
+    let order: SalesOrder = db
+        .run(move |conn| {
+            conn.transaction(|conn| {
+                let count: i64 = sales_orders::table
+                    .filter(sales_orders::sales_order.eq(&order.sales_order))
+                    .count()
+                    .get_result(conn)?;
+                if count > 0 {
+                    return Err(DataBaseError::ExistingSalesOrder);
+                }
+
+                diesel::insert_into(sales_orders::table)
+                    .values(&*order)
+                    .execute(conn)?;
+                // units can be built before their order is entered
+                refresh(conn, Some(&order.sales_order))?;
+                find(conn, &order.sales_order)
+            })
+        })
+        .await?;
+    Ok(Created::new("/").body(Json(order)))
+}
+
+#[post("/sales_orders/<sales_order>", data = "<update>")]
+pub(super) async fn update_sales_order(
+    db: Db,
+    sales_order: String,
+    mut update: Json<SalesOrderUpdate>,
+) -> Result<Json<SalesOrder>> {
+    if let Some(expected_units) = update.expected_units {
+        check_expected_units(expected_units)?;
+    }
+    update.last_updated = Some(time_default());
+    if update
+        .status
+        .is_some_and(|status| status != SalesOrderStatus::Cancelled)
+    {
+        update.status = Some(SalesOrderStatus::Open);
+    }
+    db.run(move |conn| {
+        conn.transaction(|conn| {
+            let order = find(conn, &sales_order)?;
+            diesel::update(sales_orders::table.find(order.id))
+                .set(&*update)
+                .execute(conn)?;
+            refresh(conn, Some(&sales_order))?;
+            Ok(find(conn, &sales_order)?.into())
+        })
+    })
+    .await
+}
+
+/// The forms keep their `sales_order`, it just no longer has an order behind it
+#[delete("/sales_orders/<sales_order>")]
+pub(super) async fn delete_sales_order(db: Db, sales_order: String, _admin: Admin) -> Result<()> {
+    db.run(move |conn| {
+        diesel::delete(sales_orders::table.filter(sales_orders::sales_order.eq(sales_order)))
+            .execute(conn)?;
+        Ok(())
+    })
+    .await
+}
+
+#[test]
+fn test_status_of() {
+    use SalesOrderStatus::*;
+    assert_eq!(SalesOrderStatus::of(3, 0, 0), Open);
+    assert_eq!(SalesOrderStatus::of(3, 2, 0), InProgress);
+    assert_eq!(SalesOrderStatus::of(3, 3, 2), InProgress);
+    assert_eq!(SalesOrderStatus::of(3, 3, 3), Complete);
+    // extra units built as spares don't hold the order up
+    assert_eq!(SalesOrderStatus::of(3, 5, 3), Complete);
+}
@@ -0,0 +1,321 @@
+use crate::admin_pwd::Admin;
+use crate::json_text::JsonText;
+use crate::Config;
+
+use rocket::response::status::Created;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+
+use rocket_sync_db_pools::diesel;
+
+use crate::time::Time;
+
+use self::diesel::prelude::*;
+
+use super::search::SearchForm;
+use super::*;
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+#[serde(crate = "rocket::serde")]
+pub struct SavedSearch {
+    pub id: i32,
+    // the name used to look the search up ex 'pending-qc2'
+    pub name: String,
+    // initials of whoever saved it
+    pub owner: String,
+    // shared searches are visible to everyone, otherwise only to the owner
+    pub shared: bool,
+    pub search: String,
+    pub order_table: Option<String>,
+    pub ascending: bool,
+    // the database view columns that are visible
+    pub visible_columns: Option<JsonText>,
+    pub creation_date: Time,
+    pub last_updated: Time,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name = super::schema::saved_searches)]
+pub struct NewSavedSearch {
+    pub name: String,
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub shared: bool,
+    pub search: String,
+    #[serde(default)]
+    pub order_table: Option<String>,
+    #[serde(default = "ascending_default")]
+    pub ascending: bool,
+    #[serde(default)]
+    pub visible_columns: Option<JsonText>,
+    #[serde(skip_deserializing)]
+    #[serde(default = "time_default")]
+    pub creation_date: Time,
+    #[serde(skip_deserializing)]
+    #[serde(default = "time_default")]
+    pub last_updated: Time,
+}
+
+fn ascending_default() -> bool {
+    true
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, AsChangeset)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name = super::schema::saved_searches)]
+#[serde(default)]
+pub struct SavedSearchUpdate {
+    #[serde(skip_deserializing)]
+    pub last_updated: Option<Time>,
+    pub name: Option<String>,
+    pub shared: Option<bool>,
+    pub search: Option<String>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_table: Option<Option<String>>,
+    pub ascending: Option<bool>,
+    #[serde(deserialize_with = "update::deserialize_optional_field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visible_columns: Option<Option<JsonText>>,
+}
+
+/// Builds the query a saved search runs so a broken search is refused when it's saved
+/// rather than when it's run
+fn verify_search(config: &Config, search: Option<&str>, order_table: Option<&str>) -> Result<()> {
+    search::build_query(
+        config,
+        &SearchForm {
+            limit: None,
+            offset: None,
+            search,
+            order_table,
+            ascending: None,
+            columns: None,
+        },
+    )?;
+    Ok(())
+}
+
+/// columns are the ones shown in the database view so they are checked against the config
+fn verify_columns(config: &Config, columns: &Option<JsonText>) -> Result<()> {
+    let Some(columns) = columns else {
+        return Ok(());
+    };
+    let known = config.typed().map(|model| &model.database.columns);
+    let Some(columns) = columns.0.as_array() else {
+        return Err(DataBaseError::InvalidColumn(columns.0.to_string()));
+    };
+    for column in columns {
+        match column.as_str() {
+            Some(name) if known.is_some_and(|known| known.contains_key(name)) => {}
+            _ => return Err(DataBaseError::InvalidColumn(column.to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// The columns a saved search loads for its visible columns. Columns that aren't stored
+/// are worked out from the answers so those are loaded along with them
+fn search_columns(config: &Config, columns: &Option<JsonText>) -> Option<String> {
+    let columns = columns.as_ref()?.0.as_array()?;
+    let mut loaded = Vec::new();
+    for column in columns.iter().filter_map(serde_json::Value::as_str) {
+        let stored = config
+            .typed()
+            .and_then(|model| model.database.columns.get(column))
+            .is_some_and(|column| column.db_column);
+        let column = if stored { column } else { "qc_answers" };
+        if !loaded.contains(&column) {
+            loaded.push(column);
+        }
+    }
+    Some(loaded.join(","))
+}
+
+/// There are no user accounts, `owner` is whichever initials the caller passes so this
+/// only keeps other people's searches out of the way and isn't access control
+fn visible_to(
+    owner: Option<&str>,
+) -> Box<
+    dyn BoxableExpression<
+        saved_searches::table,
+        diesel::sqlite::Sqlite,
+        SqlType = diesel::sql_types::Bool,
+    >,
+> {
+    match owner {
+        Some(owner) => Box::new(
+            saved_searches::shared
+                .eq(true)
+                .or(saved_searches::owner.eq(owner.to_owned())),
+        ),
+        None => Box::new(saved_searches::shared.eq(true)),
+    }
+}
+
+fn find_saved_search(
+    conn: &mut diesel::SqliteConnection,
+    name: &str,
+    owner: Option<&str>,
+) -> Result<SavedSearch> {
+    // a users own search takes priority over a shared one with the same name
+    if let Some(owner) = owner {
+        let own = saved_searches::table
+            .filter(saved_searches::name.eq(name))
+            .filter(saved_searches::owner.eq(owner))
+            .first(conn)
+            .optional()?;
+        if let Some(own) = own {
+            return Ok(own);
+        }
+    }
+    Ok(saved_searches::table
+        .filter(saved_searches::name.eq(name))
+        .filter(saved_searches::shared.eq(true))
+        .first(conn)?)
+}
+
+#[get("/saved_searches?<owner>")]
+pub(super) async fn list_saved_searches(
+    db: Db,
+    owner: Option<String>,
+) -> Result<Json<Vec<SavedSearch>>> {
+    db.run(move |conn| {
+        Ok(saved_searches::table
+            .filter(visible_to(owner.as_deref()))
+            .order(saved_searches::name.asc())
+            .load(conn)?
+            .into())
+    })
+    .await
+}
+
+#[get("/get_saved_search/<name>?<owner>")]
+pub(super) async fn get_saved_search(
+    db: Db,
+    name: String,
+    owner: Option<String>,
+) -> Option<Json<SavedSearch>> {
+    db.run(move |conn| find_saved_search(conn, &name, owner.as_deref()))
+        .await
+        .map(Json)
+        .ok()
+}
+
+#[post("/new_saved_search", data = "<saved>")]
+pub(super) async fn new_saved_search(
+    db: Db,
+    config: &Config,
+    saved: Json<NewSavedSearch>,
+) -> Result<Created<Json<SavedSearch>>> {
+    verify_search(config, Some(&saved.search), saved.order_table.as_deref())?;
+    verify_columns(config, &saved.visible_columns)?;
+    if saved.owner.trim().is_empty() && !saved.shared {
+        return Err(DataBaseError::SavedSearchWithoutOwner);
+    }
+
+    let saved: Json<SavedSearch> = db
+        .run(move |conn| {
+            let count: i64 = saved_searches::table
+                .filter(saved_searches::name.eq(&saved.name))
+                .filter(saved_searches::owner.eq(&saved.owner))
+                .count()
+                .get_result(conn)?;
+            if count > 0 {
+                return Err(DataBaseError::ExistingSavedSearch);
+            }
+
+            diesel::insert_into(saved_searches::table)
+                .values(&*saved)
+                .execute(conn)?;
+
+            let res: SavedSearch = saved_searches::table
+                .order(saved_searches::id.desc())
+                .first(conn)?;
+
+            Result::<Json<SavedSearch>>::Ok(res.into())
+        })
+        .await?;
+    Ok(Created::new("/").body(saved))
+}
+
+#[post("/update_saved_search/<id>?<owner>", data = "<update>")]
+pub(super) async fn update_saved_search(
+    db: Db,
+    config: &Config,
+    id: i32,
+    owner: Option<String>,
+    admin: Option<Admin>,
+    mut update: Json<SavedSearchUpdate>,
+) -> Result<Json<SavedSearch>> {
+    verify_search(
+        config,
+        update.search.as_deref(),
+        update.order_table.clone().flatten().as_deref(),
+    )?;
+    if let Some(columns) = &update.visible_columns {
+        verify_columns(config, columns)?;
+    }
+    update.last_updated = Some(time_default());
+    db.run(move |conn| {
+        let saved: SavedSearch = saved_searches::table.find(id).first(conn)?;
+        if admin.is_none() && owner.as_deref() != Some(saved.owner.as_str()) {
+            return Err(DataBaseError::NotSavedSearchOwner);
+        }
+
+        diesel::update(saved_searches::table.find(id))
+            .set(&*update)
+            .execute(conn)?;
+        Ok(saved_searches::table
+            .find(id)
+            .first::<SavedSearch>(conn)?
+            .into())
+    })
+    .await
+}
+
+#[delete("/delete_saved_search/<id>?<owner>")]
+pub(super) async fn delete_saved_search(
+    db: Db,
+    id: i32,
+    owner: Option<String>,
+    admin: Option<Admin>,
+) -> Result<()> {
+    db.run(move |conn| {
+        let saved: SavedSearch = saved_searches::table.find(id).first(conn)?;
+        if admin.is_none() && owner.as_deref() != Some(saved.owner.as_str()) {
+            return Err(DataBaseError::NotSavedSearchOwner);
+        }
+
+        diesel::delete(saved_searches::table.find(id)).execute(conn)?;
+        Ok(())
+    })
+    .await
+}
+
+#[get("/run_saved_search/<name>?<owner>&<limit>&<offset>")]
+pub(super) async fn run_saved_search(
+    db: Db,
+    config: &Config,
+    name: String,
+    owner: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Json<search::projection::SearchResults>> {
+    let saved = db
+        .run(move |conn| find_saved_search(conn, &name, owner.as_deref()))
+        .await?;
+
+    let columns = search_columns(config, &saved.visible_columns);
+    let form = SearchForm {
+        limit,
+        offset,
+        search: Some(&saved.search),
+        order_table: saved.order_table.as_deref(),
+        ascending: Some(saved.ascending),
+        columns: columns.as_deref(),
+    };
+    let results = search::run_search(&db, config, &form).await?;
+    Ok(Json(search::projection::project(results, form.columns)?))
+}
@@ -24,5 +24,107 @@ diesel::table! {
         drive_size -> Text,
         tech_notes -> Text,
         metadata -> Nullable<Text>,
+        qc_answer_details -> Text,
+        checklist_version -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    checklist_versions (id) {
+        id -> Integer,
+        definition -> Text,
+        creation_date -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    config_changes (id) {
+        id -> Integer,
+        change -> Text,
+        creation_date -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    device_models (id) {
+        id -> Integer,
+        manufacturer -> Text,
+        model -> Text,
+        match_key -> Text,
+        form_factor -> Nullable<Text>,
+        build_type -> Nullable<Text>,
+        drive_type -> Nullable<Text>,
+        drive_size -> Nullable<Text>,
+        ram_type -> Nullable<Text>,
+        ram_size -> Nullable<Text>,
+        creation_date -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    device_model_spellings (match_key) {
+        match_key -> Text,
+        device_model -> Integer,
+    }
+}
+
+diesel::table! {
+    sales_orders (id) {
+        id -> Integer,
+        sales_order -> Text,
+        customer -> Text,
+        expected_units -> Integer,
+        due_date -> Nullable<TimestamptzSqlite>,
+        status -> Text,
+        creation_date -> TimestamptzSqlite,
+        last_updated -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    unit_events (id) {
+        id -> Integer,
+        form_id -> Integer,
+        event -> Text,
+        location -> Nullable<Text>,
+        carrier -> Nullable<Text>,
+        tracking_number -> Nullable<Text>,
+        notes -> Text,
+        recorded_by -> Text,
+        event_date -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    saved_searches (id) {
+        id -> Integer,
+        name -> Text,
+        owner -> Text,
+        shared -> Bool,
+        search -> Text,
+        order_table -> Nullable<Text>,
+        ascending -> Bool,
+        visible_columns -> Nullable<Text>,
+        creation_date -> TimestamptzSqlite,
+        last_updated -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    copy_sessions (session_id) {
+        session_id -> Text,
+        producer -> Nullable<Text>,
+        disconnected -> Nullable<TimestamptzSqlite>,
+        token -> Text,
+        build_location -> Nullable<Text>,
+        last_active -> Nullable<TimestamptzSqlite>,
+    }
+}
+
+diesel::table! {
+    copy_session_items (id) {
+        id -> Integer,
+        session_id -> Text,
+        data -> Text,
     }
 }
@@ -0,0 +1,150 @@
+use serde_json::Value;
+
+use crate::Config;
+
+/// The columns that store keys of an enumeration in the config
+const ENUMERATION_COLUMNS: [(&str, &str); 9] = [
+    ("build_location", "build_locations"),
+    ("build_type", "build_types"),
+    ("drive_type", "drive_types"),
+    ("drive_size", "drive_sizes"),
+    ("operating_system", "operating_systems"),
+    ("processor_gen", "processor_gens"),
+    ("processor_type", "processor_types"),
+    ("ram_size", "ram_sizes"),
+    ("ram_type", "ram_types"),
+];
+
+/// The enumeration in the config that a column stores the keys of
+pub fn enumeration(column: &str) -> Option<&'static str> {
+    ENUMERATION_COLUMNS
+        .iter()
+        .find(|(c, _)| *c == column)
+        .map(|(_, enumeration)| *enumeration)
+}
+
+/// The column storing the keys of an enumeration, the reverse of `enumeration`
+pub fn column(enumeration: &str) -> Option<&'static str> {
+    ENUMERATION_COLUMNS
+        .iter()
+        .find(|(_, e)| *e == enumeration)
+        .map(|(column, _)| *column)
+}
+
+/// Columns whose enumeration values have a `size_bytes` that can be compared numerically
+pub fn is_sized(column: &str) -> bool {
+    matches!(column, "ram_size" | "drive_size")
+}
+
+/// Finds the stored key for a value, either the key itself or the
+/// display name given by `values.<key>.name` (case insensitive)
+pub fn resolve_key(config: &Config, enumeration: &str, value: &str) -> Option<String> {
+    let values = &config.enumeration(enumeration)?.values;
+    let value = value.trim();
+    values
+        .keys()
+        .find(|key| *key == value)
+        .or_else(|| values.keys().find(|key| key.eq_ignore_ascii_case(value)))
+        .or_else(|| {
+            values
+                .iter()
+                .find(|(_, val)| val.name.trim().eq_ignore_ascii_case(value))
+                .map(|(key, _)| key)
+        })
+        .cloned()
+}
+
+/// Maps a string value onto the stored key if it names one, otherwise leaves it untouched
+pub fn resolve_value(config: &Config, enumeration: &str, value: Value) -> Value {
+    match &value {
+        Value::String(str) => match resolve_key(config, enumeration, str) {
+            Some(key) => Value::String(key),
+            None => value,
+        },
+        _ => value,
+    }
+}
+
+/// Parses size literals like `16GiB`, `16 GiB`, `1.5TB` or `500gb` into bytes
+pub fn parse_size(str: &str) -> Option<u64> {
+    let str = str.trim();
+    let split = str
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(str.len());
+    let (number, unit) = str.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000u64.pow(2),
+        "gb" => 1000u64.pow(3),
+        "tb" => 1000u64.pow(4),
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64).round() as u64)
+}
+
+/// The number of bytes a search value refers to. Numbers are taken as bytes,
+/// strings can be a stored key, a display name or a size literal
+pub fn size_bytes(config: &Config, enumeration: &str, value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(num) => num.as_u64(),
+        Value::String(str) => match resolve_key(config, enumeration, str) {
+            Some(key) => {
+                config
+                    .enumeration(enumeration)?
+                    .values
+                    .get(&key)?
+                    .size_bytes
+            }
+            None => parse_size(str),
+        },
+        _ => None,
+    }
+}
+
+/// Every key of the enumeration whose `size_bytes` satisfies `pred`
+pub fn keys_by_size(config: &Config, enumeration: &str, pred: impl Fn(u64) -> bool) -> Vec<String> {
+    config
+        .enumeration(enumeration)
+        .into_iter()
+        .flat_map(|enumeration| enumeration.values.iter())
+        .filter(|(_, val)| val.size_bytes.is_some_and(&pred))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+#[test]
+fn test_aliases() {
+    let config = Config::load_from_file("./config.json5").expect("Failed to load config file");
+
+    assert_eq!(
+        resolve_key(&config, "operating_systems", "windows 11").as_deref(),
+        Some("win11")
+    );
+    assert_eq!(
+        resolve_key(&config, "ram_sizes", "16 GiB").as_deref(),
+        Some("GiB016")
+    );
+    assert_eq!(
+        resolve_key(&config, "ram_sizes", "GiB016").as_deref(),
+        Some("GiB016")
+    );
+    assert_eq!(resolve_key(&config, "ram_sizes", "17 GiB"), None);
+
+    assert_eq!(parse_size("16GiB"), Some(17179869184));
+    assert_eq!(parse_size("1.5 TB"), Some(1500000000000));
+    assert_eq!(parse_size("16 parsecs"), None);
+
+    let mut keys = keys_by_size(&config, "ram_sizes", |s| {
+        s >= size_bytes(&config, "ram_sizes", &Value::String("32GiB".into())).unwrap()
+    });
+    keys.sort();
+    assert_eq!(keys, ["GiB032", "GiB048", "GiB064", "GiB128"]);
+}
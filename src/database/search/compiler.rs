@@ -6,6 +6,7 @@ use serde_json::Value;
 use super::tokenizer::{Token, TokenErrorFull, TokenFull, Tokenizer};
 
 pub struct ExpressionParser<'a, 'b, T, E> {
+    expression: &'a str,
     tokenizer: Peekable<Tokenizer<'a>>,
     visitor: &'b mut dyn Visitor<T, E>,
 }
@@ -20,7 +21,10 @@ pub trait Visitor<T, E> {
     fn eq(&mut self, ident: String, value: Value) -> Result<T, E>;
     fn lt(&mut self, ident: String, value: Value) -> Result<T, E>;
     fn gt(&mut self, ident: String, value: Value) -> Result<T, E>;
+    fn lt_eq(&mut self, ident: String, value: Value) -> Result<T, E>;
+    fn gt_eq(&mut self, ident: String, value: Value) -> Result<T, E>;
     fn colon(&mut self, ident: String, value: Value) -> Result<T, E>;
+    fn regex(&mut self, ident: String, value: Value) -> Result<T, E>;
     fn between(&mut self, low_value: Value, ident: String, high_value: Value) -> Result<T, E>;
 
     fn or(&mut self, ls: T, rs: T) -> Result<T, E>;
@@ -135,11 +139,119 @@ macro_rules! pop_stack {
 impl<'a, 'b, T, E> ExpressionParser<'a, 'b, T, E> {
     pub fn new(expression: &'a str, visitor: &'b mut impl Visitor<T, E>) -> Self {
         Self {
+            expression,
             tokenizer: Tokenizer::new(expression).peekable(),
             visitor,
         }
     }
 
+    /// Parses `;` separated sub queries each with an optional `"label":` prefix.
+    /// Sub queries without a label are labeled with their own text
+    pub fn parse_many(&mut self) -> Result<Vec<(String, T)>, ExpressionParserError<E>> {
+        let mut queries = Vec::new();
+        loop {
+            while tok_matches!(self.tokenizer.peek(), Token::Semicolon) {
+                self.tokenizer.next();
+            }
+            let start = match self.tokenizer.peek() {
+                None => break,
+                Some(Err(err)) => {
+                    return Err(ExpressionParserError::TokenizerError(err.to_owned()))
+                }
+                Some(Ok(tok)) => tok.start.byte_index,
+            };
+
+            let mut label = None;
+            let mut lookahead = self.tokenizer.clone();
+            if let Some(Ok(TokenFull {
+                data: Token::Value(Value::String(str)),
+                ..
+            })) = lookahead.next()
+            {
+                if let Some(Ok(TokenFull {
+                    data: Token::Colon, ..
+                })) = lookahead.next()
+                {
+                    label = Some(str);
+                    self.tokenizer = lookahead;
+                }
+            }
+            let start = match (&label, self.tokenizer.peek()) {
+                (Some(_), Some(Ok(tok))) => tok.start.byte_index,
+                _ => start,
+            };
+
+            let expr = self.parse()?;
+
+            let end = match self.tokenizer.peek() {
+                None => self.expression.len(),
+                Some(Err(err)) => {
+                    return Err(ExpressionParserError::TokenizerError(err.to_owned()))
+                }
+                Some(Ok(tok)) => tok.start.byte_index,
+            };
+            if let Some(tok) = self.tokenizer.next() {
+                let tok = unwrap_token!(Some(tok));
+                expect_tok!(tok, Token::Semicolon);
+            }
+
+            let label = label.unwrap_or_else(|| self.expression[start..end].trim().to_owned());
+            queries.push((label, expr));
+        }
+        Ok(queries)
+    }
+
+    /// The value of a `:` pattern. Touching `*` tokens are wildcards joining the values
+    /// and identifiers around them, so `SHID*` and `*"0023"*` both become LIKE patterns
+    fn parse_pattern(&mut self) -> Result<Value, ExpressionParserError<E>> {
+        let first = unwrap_token!(self.tokenizer.next());
+        let mut end = first.end;
+        let mut rest = Vec::new();
+
+        loop {
+            let touching = match self.tokenizer.peek() {
+                Some(Ok(tok)) => {
+                    tok.start == end
+                        && matches!(tok.data, Token::Star | Token::Value(_) | Token::Ident(_))
+                }
+                _ => false,
+            };
+            if !touching {
+                break;
+            }
+            let tok = unwrap_token!(self.tokenizer.next());
+            end = tok.end;
+            rest.push(tok);
+        }
+
+        if first.data != Token::Star && !rest.iter().any(|tok| tok.data == Token::Star) {
+            if let Some(extra) = rest.into_iter().next() {
+                return Err(ExpressionParserError::UnexpectedTokenReason {
+                    got: extra,
+                    expected: stringify!(Token::Star),
+                });
+            }
+            return Ok(expect_value!(first));
+        }
+
+        let mut pattern = String::new();
+        for tok in std::iter::once(first).chain(rest) {
+            match tok.data {
+                Token::Star => pattern.push('%'),
+                Token::Ident(ident) => pattern.push_str(&ident),
+                Token::Value(Value::String(str)) => pattern.push_str(&str),
+                Token::Value(Value::Number(num)) => pattern.push_str(&num.to_string()),
+                _ => {
+                    return Err(ExpressionParserError::UnexpectedTokenReason {
+                        got: tok,
+                        expected: stringify!(Token::Star | Token::Ident | Token::Value),
+                    })
+                }
+            }
+        }
+        Ok(Value::String(pattern))
+    }
+
     pub fn parse(&mut self) -> Result<T, ExpressionParserError<E>> {
         #[derive(Debug)]
         enum State {
@@ -231,15 +343,33 @@ impl<'a, 'b, T, E> ExpressionParser<'a, 'b, T, E> {
                             let value = expect_value!(unwrap_token!(self.tokenizer.next()));
                             unwrap_visitor!(self.visitor.lt(ident, value))
                         }
-                        Token::Colon => {
+                        Token::GtEq => {
                             let value = expect_value!(unwrap_token!(self.tokenizer.next()));
+                            unwrap_visitor!(self.visitor.gt_eq(ident, value))
+                        }
+                        Token::LtEq => {
+                            let value = expect_value!(unwrap_token!(self.tokenizer.next()));
+                            unwrap_visitor!(self.visitor.lt_eq(ident, value))
+                        }
+                        Token::Colon => {
+                            let value = self.parse_pattern()?;
                             unwrap_visitor!(self.visitor.colon(ident, value))
                         }
+                        Token::Carrot => {
+                            let value = expect_value!(unwrap_token!(self.tokenizer.next()));
+                            unwrap_visitor!(self.visitor.regex(ident, value))
+                        }
                         _ => {
                             return Err(ExpressionParserError::UnexpectedTokenReason {
                                 got: operator,
                                 expected: stringify!(
-                                    Token::Eq | Token::Gt | Token::Lt | Token::Colon
+                                    Token::Eq
+                                        | Token::Gt
+                                        | Token::Lt
+                                        | Token::GtEq
+                                        | Token::LtEq
+                                        | Token::Colon
+                                        | Token::Carrot
                                 ),
                             })
                         }
@@ -293,9 +423,18 @@ fn test_parser() {
         fn gt(&mut self, ident: String, value: Value) -> Result<String, ()> {
             Ok(format!("({}>{:#?})", ident, value))
         }
+        fn lt_eq(&mut self, ident: String, value: Value) -> Result<String, ()> {
+            Ok(format!("({}<={:#?})", ident, value))
+        }
+        fn gt_eq(&mut self, ident: String, value: Value) -> Result<String, ()> {
+            Ok(format!("({}>={:#?})", ident, value))
+        }
         fn colon(&mut self, ident: String, value: Value) -> Result<String, ()> {
             Ok(format!("({}:{:#?})", ident, value))
         }
+        fn regex(&mut self, ident: String, value: Value) -> Result<String, ()> {
+            Ok(format!("({}^{:#?})", ident, value))
+        }
         fn between(
             &mut self,
             low_value: Value,
@@ -325,3 +464,30 @@ fn test_parser() {
     drop(expr);
     println!("{:#?}", res);
 }
+
+#[test]
+fn test_patterns_and_sub_queries() {
+    let mut visitor = super::CompilerVisitor {};
+    let res = ExpressionParser::new(
+        r#""serials": item_serial: SHID*; make_model ^ "^HP" | oem_serial: *"12"*"#,
+        &mut visitor,
+    )
+    .parse_many()
+    .unwrap();
+    let res = serde_json::to_value(&res).unwrap();
+    assert_eq!(
+        res,
+        serde_json::json!([
+            ["serials", {"type": "Colon", "data": ["item_serial", "SHID%"]}],
+            ["make_model ^ \"^HP\" | oem_serial: *\"12\"*", {"type": "Or", "data": [
+                {"type": "Regex", "data": ["make_model", "^HP"]},
+                {"type": "Colon", "data": ["oem_serial", "%12%"]}
+            ]}]
+        ])
+    );
+
+    let mut visitor = super::CompilerVisitor {};
+    assert!(ExpressionParser::new("item_serial: SHID *", &mut visitor)
+        .parse_many()
+        .is_err());
+}
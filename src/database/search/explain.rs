@@ -0,0 +1,335 @@
+use diesel::query_builder::{AstPass, Query, QueryFragment, QueryId};
+use diesel::sql_types::{Integer, Text, Untyped};
+use diesel::sqlite::{Sqlite, SqliteConnection};
+use diesel::QueryResult;
+
+use rocket::form::Form;
+use rocket::serde::json::Json;
+
+use rocket_sync_db_pools::diesel;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::compiler::{ExpressionParser, Visitor};
+use super::*;
+use crate::config::QuestionKind;
+
+#[derive(Debug, Clone, Serialize, thiserror::Error)]
+#[serde(tag = "type", content = "data")]
+pub enum SearchWarning {
+    #[error("Text column '{column}' is compared with the number {value}")]
+    TextComparedWithNumber { column: String, value: Value },
+    #[error("Numeric column '{column}' is compared with the text {value}")]
+    NumberComparedWithText { column: String, value: Value },
+    #[error("Boolean column '{column}' is compared with the non boolean {value}")]
+    BooleanComparedWithNonBoolean { column: String, value: Value },
+    #[error(
+        "{value} is not a value of '{enumeration}' in the config so '{column}' will never match it"
+    )]
+    UnknownEnumValue {
+        column: String,
+        enumeration: &'static str,
+        value: Value,
+    },
+    #[error("Comparing '{column}' with null is always false, use '{column} = null' instead")]
+    AlwaysFalseNull { column: String },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Eq,
+    Ordering,
+    Like,
+    Regex,
+}
+
+#[derive(Debug, PartialEq, PartialOrd)]
+enum Precedence {
+    Or,
+    And,
+    Atom,
+}
+
+type Normalized = (String, Precedence);
+
+/// Rebuilds a search in its canonical form, with config display names mapped onto
+/// their keys, while collecting anything that looks like a mistake
+struct ExplainVisitor<'a> {
+    config: &'a Config,
+    warnings: Vec<SearchWarning>,
+}
+
+impl<'a> ExplainVisitor<'a> {
+    fn check(
+        &mut self,
+        ident: &str,
+        operator: Operator,
+        value: Value,
+    ) -> Result<Value, VisitorError> {
+        let kind = self.config.question(ident).map(|question| question.kind);
+        if verify_column(ident).is_err() && kind.unwrap_or_default() != QuestionKind::Check {
+            if kind == Some(QuestionKind::Number) && value.is_string() {
+                self.warnings.push(SearchWarning::NumberComparedWithText {
+                    column: ident.to_owned(),
+                    value: value.clone(),
+                })
+            }
+            return Ok(value);
+        }
+        let column = verify_column(ident).map_err(|c| VisitorError::InvalidColumn(c.into()))?;
+        let column_name = column.column_name.to_owned();
+
+        if value.is_null() {
+            if !matches!(operator, Operator::Eq) {
+                self.warnings.push(SearchWarning::AlwaysFalseNull {
+                    column: column_name,
+                });
+            }
+            return Ok(value);
+        }
+
+        let enumeration = aliases::enumeration(column.column_name);
+        let sized = aliases::is_sized(column.column_name);
+
+        match (&column.col_type, &value) {
+            (ColumnType::Text, Value::Number(_)) if !sized => {
+                self.warnings.push(SearchWarning::TextComparedWithNumber {
+                    column: column_name,
+                    value: value.clone(),
+                })
+            }
+            (ColumnType::PrimaryId | ColumnType::Number | ColumnType::Real, Value::String(_)) => {
+                self.warnings.push(SearchWarning::NumberComparedWithText {
+                    column: column_name,
+                    value: value.clone(),
+                })
+            }
+            (ColumnType::Boolean, Value::Bool(_)) => {}
+            (ColumnType::Boolean, _) => {
+                self.warnings
+                    .push(SearchWarning::BooleanComparedWithNonBoolean {
+                        column: column_name,
+                        value: value.clone(),
+                    })
+            }
+            (_, Value::String(str)) => {
+                if let Some(enumeration) = enumeration {
+                    let is_pattern = matches!(operator, Operator::Regex)
+                        || (matches!(operator, Operator::Like) && str.contains(['%', '_']));
+                    let known = aliases::resolve_key(self.config, enumeration, str).is_some()
+                        || (sized && aliases::parse_size(str).is_some());
+                    if !is_pattern && !known {
+                        self.warnings.push(SearchWarning::UnknownEnumValue {
+                            column: column_name,
+                            enumeration,
+                            value: value.clone(),
+                        })
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(match enumeration {
+            Some(enumeration) => aliases::resolve_value(self.config, enumeration, value),
+            None => value,
+        })
+    }
+
+    fn binary(
+        &mut self,
+        ident: String,
+        operator: Operator,
+        symbol: &str,
+        value: Value,
+    ) -> Result<Normalized, VisitorError> {
+        let value = self.check(&ident, operator, value)?;
+        Ok((format!("{ident} {symbol} {value}"), Precedence::Atom))
+    }
+}
+
+fn wrap((expr, precedence): Normalized, min: Precedence) -> String {
+    if precedence < min {
+        format!("({expr})")
+    } else {
+        expr
+    }
+}
+
+impl<'a> Visitor<Normalized, VisitorError> for ExplainVisitor<'a> {
+    fn eq(&mut self, ident: String, value: Value) -> Result<Normalized, VisitorError> {
+        self.binary(ident, Operator::Eq, "=", value)
+    }
+
+    fn lt(&mut self, ident: String, value: Value) -> Result<Normalized, VisitorError> {
+        self.binary(ident, Operator::Ordering, "<", value)
+    }
+
+    fn gt(&mut self, ident: String, value: Value) -> Result<Normalized, VisitorError> {
+        self.binary(ident, Operator::Ordering, ">", value)
+    }
+
+    fn lt_eq(&mut self, ident: String, value: Value) -> Result<Normalized, VisitorError> {
+        self.binary(ident, Operator::Ordering, "<=", value)
+    }
+
+    fn gt_eq(&mut self, ident: String, value: Value) -> Result<Normalized, VisitorError> {
+        self.binary(ident, Operator::Ordering, ">=", value)
+    }
+
+    fn colon(&mut self, ident: String, value: Value) -> Result<Normalized, VisitorError> {
+        self.binary(ident, Operator::Like, ":", value)
+    }
+
+    fn regex(&mut self, ident: String, value: Value) -> Result<Normalized, VisitorError> {
+        self.binary(ident, Operator::Regex, "^", value)
+    }
+
+    fn between(
+        &mut self,
+        low_value: Value,
+        ident: String,
+        high_value: Value,
+    ) -> Result<Normalized, VisitorError> {
+        let low_value = self.check(&ident, Operator::Ordering, low_value)?;
+        let high_value = self.check(&ident, Operator::Ordering, high_value)?;
+        Ok((
+            format!("{low_value} < {ident} < {high_value}"),
+            Precedence::Atom,
+        ))
+    }
+
+    fn or(&mut self, ls: Normalized, rs: Normalized) -> Result<Normalized, VisitorError> {
+        Ok((
+            format!(
+                "{} | {}",
+                wrap(ls, Precedence::Or),
+                wrap(rs, Precedence::And)
+            ),
+            Precedence::Or,
+        ))
+    }
+
+    fn and(&mut self, ls: Normalized, rs: Normalized) -> Result<Normalized, VisitorError> {
+        Ok((
+            format!(
+                "{} & {}",
+                wrap(ls, Precedence::And),
+                wrap(rs, Precedence::Atom)
+            ),
+            Precedence::And,
+        ))
+    }
+
+    fn not(&mut self, expr: Normalized) -> Result<Normalized, VisitorError> {
+        Ok((
+            format!("!{}", wrap(expr, Precedence::Atom)),
+            Precedence::Atom,
+        ))
+    }
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct PlanStep {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Integer)]
+    pub parent: i32,
+    #[diesel(sql_type = Text)]
+    pub detail: String,
+}
+
+/// `EXPLAIN QUERY PLAN` of a query, run with the query's own binds
+struct ExplainQueryPlan<Q>(Q);
+
+impl<Q> QueryId for ExplainQueryPlan<Q> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Q: QueryFragment<Sqlite>> QueryFragment<Sqlite> for ExplainQueryPlan<Q> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        out.push_sql("EXPLAIN QUERY PLAN ");
+        self.0.walk_ast(out.reborrow())
+    }
+}
+
+impl<Q> Query for ExplainQueryPlan<Q> {
+    type SqlType = Untyped;
+}
+
+impl<Q> RunQueryDsl<SqliteConnection> for ExplainQueryPlan<Q> {}
+
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub normalized: Option<String>,
+    pub sql: String,
+    pub warnings: Vec<SearchWarning>,
+    pub plan: Vec<PlanStep>,
+    // sqlite doesn't estimate rows in its query plans so this is an actual count
+    pub row_count: i64,
+}
+
+#[post("/explain", data = "<search>")]
+pub(in crate::database) async fn explain(
+    db: Db,
+    config: &Config,
+    search: Form<SearchForm<'_>>,
+) -> Result<Json<Explanation>> {
+    let mut visitor = ExplainVisitor {
+        config,
+        warnings: Vec::new(),
+    };
+    let normalized = match search.search {
+        Some(search) if !search.trim().is_empty() => {
+            let queries = ExpressionParser::new(search, &mut visitor).parse_many()?;
+            if let [(_, (query, _))] = queries.as_slice() {
+                Some(query.clone())
+            } else {
+                Some(
+                    queries
+                        .into_iter()
+                        .map(|(label, (query, _))| format!("{}: {query}", Value::String(label)))
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                )
+            }
+        }
+        _ => None,
+    };
+    let warnings = visitor.warnings;
+
+    let sql = diesel::debug_query::<Sqlite, _>(&build_query(config, &search)?).to_string();
+
+    // typed answers are compared with bound values so the plan and count run the filter
+    // itself, which is consumed by each query
+    let filters = [
+        compile_filter(config, search.search)?,
+        compile_filter(config, search.search)?,
+    ];
+
+    let (plan, row_count) = db
+        .run(move |conn| {
+            register_sql_functions(conn)?;
+            let [planned, counted] = filters;
+            let mut query = qc_forms::table.select(qc_forms::id).into_boxed();
+            if let Some(filter) = planned {
+                query = query.filter(filter);
+            }
+            let plan: Vec<PlanStep> = ExplainQueryPlan(query).load(conn)?;
+            let mut count = qc_forms::table.into_boxed();
+            if let Some(filter) = counted {
+                count = count.filter(filter);
+            }
+            Result::<_>::Ok((plan, count.count().get_result(conn)?))
+        })
+        .await?;
+
+    Ok(Json(Explanation {
+        normalized,
+        sql,
+        warnings,
+        plan,
+        row_count,
+    }))
+}
@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+
+use diesel::expression::{AppearsOnTable, Expression, SelectableExpression, ValidGrouping};
+use diesel::query_builder::{AstPass, QueryFragment, QueryId};
 use diesel::sql_types::Bool;
 use diesel::sqlite::Sqlite;
 
@@ -8,9 +12,14 @@ use rocket::serde::json::Json;
 use rocket_sync_db_pools::diesel;
 use serde_json::Value;
 
+use crate::config::QuestionKind;
 use crate::database::search::compiler::ExpressionParser;
+use crate::{Config, SharedConfig};
 
+pub mod aliases;
 pub mod compiler;
+pub mod explain;
+pub mod projection;
 pub mod tokenizer;
 
 #[derive(Debug, Serialize, thiserror::Error)]
@@ -31,6 +40,10 @@ pub enum VisitorError {
     JsonParsingError(String),
     #[error("Invalid Column: {0}")]
     InvalidColumn(String),
+    #[error("Invalid type encountered when using the regex operator: type='{0}'")]
+    InvalidTypeUsedWithRegexOperator(&'static str),
+    #[error("Invalid regex: {0}")]
+    InvalidRegex(String),
 }
 
 use self::compiler::{ExpressionParserError, Visitor};
@@ -53,8 +66,11 @@ pub enum Node {
     Eq(String, Value),
     Lt(String, Value),
     Gt(String, Value),
+    LtEq(String, Value),
+    GtEq(String, Value),
     Between(Value, String, Value),
     Colon(String, Value),
+    Regex(String, Value),
     And(Box<Node>, Box<Node>),
     Or(Box<Node>, Box<Node>),
     Not(Box<Node>),
@@ -74,10 +90,22 @@ impl Visitor<Node, Infallible> for CompilerVisitor {
         Ok(Node::Gt(ident, value))
     }
 
+    fn lt_eq(&mut self, ident: String, value: Value) -> std::result::Result<Node, Infallible> {
+        Ok(Node::LtEq(ident, value))
+    }
+
+    fn gt_eq(&mut self, ident: String, value: Value) -> std::result::Result<Node, Infallible> {
+        Ok(Node::GtEq(ident, value))
+    }
+
     fn colon(&mut self, ident: String, value: Value) -> std::result::Result<Node, Infallible> {
         Ok(Node::Colon(ident, value))
     }
 
+    fn regex(&mut self, ident: String, value: Value) -> std::result::Result<Node, Infallible> {
+        Ok(Node::Regex(ident, value))
+    }
+
     fn between(
         &mut self,
         low_value: Value,
@@ -108,11 +136,55 @@ pub(super) async fn compile(str: &str) -> Json<Result<Node, ExpressionParserErro
 }
 
 #[get("/get_post/<id>")]
-pub(super) async fn get_post(db: Db, id: i32) -> Option<Json<ExistingQCForm>> {
-    db.run(move |conn| qc_forms::table.find(id).get_result(conn))
-        .await
-        .map(Json)
-        .ok()
+pub(super) async fn get_post(
+    db: Db,
+    config: SharedConfig,
+    id: i32,
+) -> Result<Option<Json<SummarizedQCForm>>> {
+    db.run(move |conn| {
+        let Some(form) = qc_forms::table.find(id).get_result(conn).optional()? else {
+            return Ok(None);
+        };
+        Ok(Some(Json(SummarizedQCForm::load(conn, &config, form)?)))
+    })
+    .await
+}
+
+pub fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+diesel::sql_function! {
+    /// Backs sqlites `REGEXP` operator which has no implementation by default
+    fn regexp(pattern: diesel::sql_types::Text, text: diesel::sql_types::Nullable<diesel::sql_types::Text>) -> Bool;
+}
+
+/// Registers the functions searches rely on, this has to happen for every connection
+pub fn register_sql_functions(conn: &mut diesel::SqliteConnection) -> QueryResult<()> {
+    // the pattern is the same for every row so only compile it when it changes
+    let cache = std::sync::Mutex::new(None::<(String, regex::Regex)>);
+    regexp::register_impl(conn, move |pattern: String, text: Option<String>| {
+        let Some(text) = text else {
+            return false;
+        };
+        let Ok(mut cache) = cache.lock() else {
+            return false;
+        };
+        if cache.as_ref().map(|(p, _)| *p != pattern).unwrap_or(true) {
+            *cache = regex::Regex::new(&pattern).ok().map(|r| (pattern, r));
+        }
+        cache
+            .as_ref()
+            .map(|(_, regex)| regex.is_match(&text))
+            .unwrap_or(false)
+    })
 }
 
 fn to_sql_str(value: &Value) -> String {
@@ -151,6 +223,8 @@ pub enum ColumnType {
 
 pub struct ColumnInfo {
     pub column_name: &'static str,
+    // what the column is in sql, the column itself unless it is derived
+    pub expression: &'static str,
     pub nullable: bool,
     pub col_type: ColumnType,
 }
@@ -159,10 +233,30 @@ impl ColumnInfo {
     pub fn new(column_name: &'static str, nullable: bool, col_type: ColumnType) -> Self {
         Self {
             column_name,
+            expression: column_name,
             nullable,
             col_type,
         }
     }
+
+    /// A column worked out from other tables for each form, these can be searched and
+    /// sorted by but aren't part of the form itself
+    pub fn derived(
+        column_name: &'static str,
+        expression: &'static str,
+        col_type: ColumnType,
+    ) -> Self {
+        Self {
+            column_name,
+            expression,
+            nullable: true,
+            col_type,
+        }
+    }
+
+    pub fn is_derived(&self) -> bool {
+        self.expression != self.column_name
+    }
 }
 
 pub fn verify_column(column: &str) -> Result<ColumnInfo, &str> {
@@ -191,90 +285,275 @@ pub fn verify_column(column: &str) -> Result<ColumnInfo, &str> {
         "sales_order" => ColumnInfo::new("sales_order", true, ColumnType::Text),
         "tech_notes" => ColumnInfo::new("tech_notes", false, ColumnType::Text),
         "metadata" => ColumnInfo::new("metadata", false, ColumnType::Json),
+        "qc_answer_details" => ColumnInfo::new("qc_answer_details", false, ColumnType::Json),
+        "checklist_version" => ColumnInfo::new("checklist_version", true, ColumnType::Number),
+        "unit_status" => {
+            ColumnInfo::derived("unit_status", unit_event::UNIT_STATUS, ColumnType::Text)
+        }
+        "unit_status_date" => ColumnInfo::derived(
+            "unit_status_date",
+            unit_event::UNIT_STATUS_DATE,
+            ColumnType::Datetime,
+        ),
+        "unit_location" => {
+            ColumnInfo::derived("unit_location", unit_event::UNIT_LOCATION, ColumnType::Text)
+        }
+        "carrier" => ColumnInfo::derived("carrier", unit_event::CARRIER, ColumnType::Text),
+        "tracking_number" => ColumnInfo::derived(
+            "tracking_number",
+            unit_event::TRACKING_NUMBER,
+            ColumnType::Text,
+        ),
         _ => return Err(column),
     })
 }
 
-type DynExpr =
+pub(super) type DynExpr =
     Box<dyn BoxableExpression<qc_forms::table, Sqlite, SqlType = diesel::sql_types::Bool>>;
 
-struct SearchVisitor {}
-impl SearchVisitor {
-    pub fn new() -> Self {
-        Self {}
+/// Whether any answer at `path` has a value satisfying `operator`, with the compared
+/// values bound as parameters rather than written into the sql
+struct AnyValue {
+    path: String,
+    operator: &'static str,
+    values: Vec<Bound>,
+}
+
+/// A json value as sqlite stores it, answers only hold scalars so anything else is null
+enum Bound {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl From<Value> for Bound {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Bool(bool) => Bound::Bool(bool),
+            Value::Number(num) => match (num.as_i64(), num.as_f64()) {
+                (Some(int), _) => Bound::Integer(int),
+                (None, Some(real)) => Bound::Real(real),
+                (None, None) => Bound::Null,
+            },
+            Value::String(string) => Bound::Text(string),
+            Value::Null | Value::Array(_) | Value::Object(_) => Bound::Null,
+        }
     }
 }
 
-impl compiler::Visitor<DynExpr, VisitorError> for SearchVisitor {
-    fn eq(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
+impl Expression for AnyValue {
+    type SqlType = Bool;
+}
+
+impl ValidGrouping<()> for AnyValue {
+    type IsAggregate = diesel::expression::is_aggregate::Never;
+}
+
+impl AppearsOnTable<qc_forms::table> for AnyValue {}
+
+impl SelectableExpression<qc_forms::table> for AnyValue {}
+
+impl QueryId for AnyValue {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl QueryFragment<Sqlite> for AnyValue {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        use diesel::sql_types::{BigInt, Double, Text};
+        out.push_sql("EXISTS (SELECT 1 FROM json_each(qc_answer_details, ");
+        out.push_bind_param::<Text, _>(&self.path)?;
+        out.push_sql(") WHERE json_extract(value, '$.value')");
+        out.push_sql(self.operator);
+        for (i, value) in self.values.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(" AND ");
+            }
+            match value {
+                Bound::Null => out.push_sql("NULL"),
+                Bound::Bool(bool) => out.push_bind_param::<Bool, _>(bool)?,
+                Bound::Integer(int) => out.push_bind_param::<BigInt, _>(int)?,
+                Bound::Real(real) => out.push_bind_param::<Double, _>(real)?,
+                Bound::Text(string) => out.push_bind_param::<Text, _>(string)?,
+            }
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+struct SearchVisitor<'a> {
+    config: &'a Config,
+}
+impl<'a> SearchVisitor<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self { config }
+    }
+
+    /// Maps display names from the config onto the keys stored in the database
+    fn resolve(&self, column: &ColumnInfo, value: Value) -> Value {
+        match aliases::enumeration(column.column_name) {
+            Some(enumeration) => aliases::resolve_value(self.config, enumeration, value),
+            None => value,
+        }
+    }
+
+    /// For sized columns, every key whose `size_bytes` relates to the given values by `pred`
+    fn sized_keys(
+        &self,
+        column: &ColumnInfo,
+        values: &[&Value],
+        pred: impl Fn(u64, &[u64]) -> bool,
+    ) -> Option<Vec<String>> {
+        if !aliases::is_sized(column.column_name) {
+            return None;
+        }
+        let enumeration = aliases::enumeration(column.column_name)?;
+        let bytes = values
+            .iter()
+            .map(|v| aliases::size_bytes(self.config, enumeration, v))
+            .collect::<Option<Vec<_>>>()?;
+        Some(aliases::keys_by_size(self.config, enumeration, |size| {
+            pred(size, &bytes)
+        }))
+    }
+
+    fn one_of(column: &ColumnInfo, keys: Vec<String>) -> DynExpr {
         use diesel::dsl::*;
-        let column = verify_column(&ident).map_err(|c| VisitorError::InvalidColumn(c.into()))?;
+        if keys.is_empty() {
+            return Box::new(sql::<Bool>("FALSE"));
+        }
+        let list = keys
+            .iter()
+            .map(|k| to_sql_str(&Value::String(k.clone())))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if column.nullable {
+            Box::new(
+                sql::<Bool>("ifnull(")
+                    .sql(column.expression)
+                    .sql(" IN (")
+                    .sql(&list)
+                    .sql("), FALSE)"),
+            )
+        } else {
+            Box::new(
+                sql::<Bool>(column.expression)
+                    .sql(" IN (")
+                    .sql(&list)
+                    .sql(")"),
+            )
+        }
+    }
 
-        match value {
-            Value::Null => Ok(Box::new(sql::<Bool>(column.column_name).sql(" IS NULL"))),
-            value => Ok(if column.nullable {
-                Box::new(
-                    sql::<Bool>("ifnull(")
-                        .sql(column.column_name)
-                        .sql(" = ")
-                        .sql(&to_sql_str(&value))
-                        .sql(", FALSE)"),
-                )
-            } else {
-                Box::new(
-                    sql::<Bool>(column.column_name)
-                        .sql(" = ")
-                        .sql(&to_sql_str(&value)),
-                )
-            }),
+    fn binary(column: &ColumnInfo, operator: &str, value: &Value) -> DynExpr {
+        use diesel::dsl::*;
+        if column.nullable {
+            Box::new(
+                sql::<Bool>("ifnull(")
+                    .sql(column.expression)
+                    .sql(operator)
+                    .sql(&to_sql_str(value))
+                    .sql(", FALSE)"),
+            )
+        } else {
+            Box::new(
+                sql::<Bool>(column.expression)
+                    .sql(operator)
+                    .sql(&to_sql_str(value)),
+            )
         }
     }
-    fn lt(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
+
+    /// Typed questions are searched by their id, a form matches when the value of any
+    /// of its passes does. Gives the JSON path of the question's answers
+    fn answer_path(&self, ident: &str) -> Option<String> {
+        let kind = self.config.question(ident).map(|question| question.kind);
+        if verify_column(ident).is_ok() || kind.unwrap_or_default() == QuestionKind::Check {
+            return None;
+        }
+        Some(format!("$.\"{ident}\""))
+    }
+
+    fn any_value(path: &str, operator: &'static str, values: Vec<Value>) -> DynExpr {
+        Box::new(AnyValue {
+            path: path.to_owned(),
+            operator,
+            values: values.into_iter().map(Bound::from).collect(),
+        })
+    }
+
+    fn compare(
+        &self,
+        ident: String,
+        value: Value,
+        operator: &'static str,
+        pred: impl Fn(u64, u64) -> bool,
+    ) -> Result<DynExpr, VisitorError> {
         use diesel::dsl::*;
+        if let Some(path) = self.answer_path(&ident) {
+            if value.is_null() {
+                return Ok(Box::new(sql::<Bool>("FALSE")));
+            }
+            return Ok(Self::any_value(&path, operator, vec![value]));
+        }
         let column = verify_column(&ident).map_err(|c| VisitorError::InvalidColumn(c.into()))?;
 
-        match value {
-            Value::Null => Ok(Box::new(sql::<Bool>("FALSE"))),
-            value => Ok(if column.nullable {
-                Box::new(
-                    sql::<Bool>("ifnull(")
-                        .sql(column.column_name)
-                        .sql(" < ")
-                        .sql(&to_sql_str(&value))
-                        .sql(", FALSE)"),
-                )
-            } else {
-                Box::new(
-                    sql::<Bool>(column.column_name)
-                        .sql(" < ")
-                        .sql(&to_sql_str(&value)),
-                )
-            }),
+        if value.is_null() {
+            return Ok(Box::new(sql::<Bool>("FALSE")));
+        }
+        if let Some(keys) = self.sized_keys(&column, &[&value], |size, b| pred(size, b[0])) {
+            return Ok(Self::one_of(&column, keys));
         }
+        let value = self.resolve(&column, value);
+        Ok(Self::binary(&column, operator, &value))
     }
-    fn gt(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
+}
+
+impl<'a> compiler::Visitor<DynExpr, VisitorError> for SearchVisitor<'a> {
+    fn eq(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
         use diesel::dsl::*;
+        if let Some(path) = self.answer_path(&ident) {
+            return Ok(match value {
+                Value::Null => Box::new(not(Self::any_value(&path, " IS NOT NULL", Vec::new()))),
+                value => Self::any_value(&path, " = ", vec![value]),
+            });
+        }
         let column = verify_column(&ident).map_err(|c| VisitorError::InvalidColumn(c.into()))?;
 
         match value {
-            Value::Null => Ok(Box::new(sql::<Bool>("FALSE"))),
-            value => Ok(if column.nullable {
-                Box::new(
-                    sql::<Bool>("ifnull(")
-                        .sql(column.column_name)
-                        .sql(" > ")
-                        .sql(&to_sql_str(&value))
-                        .sql(", FALSE)"),
-                )
-            } else {
-                Box::new(
-                    sql::<Bool>(column.column_name)
-                        .sql(" > ")
-                        .sql(&to_sql_str(&value)),
-                )
-            }),
+            Value::Null => Ok(Box::new(sql::<Bool>(column.expression).sql(" IS NULL"))),
+            value => {
+                let value = self.resolve(&column, value);
+                // values that aren't a key (like 16GiB) match every key of that size
+                let is_key = value
+                    .as_str()
+                    .zip(aliases::enumeration(column.column_name))
+                    .map(|(v, e)| aliases::resolve_key(self.config, e, v).is_some())
+                    .unwrap_or(false);
+                if !is_key {
+                    if let Some(keys) = self.sized_keys(&column, &[&value], |s, b| s == b[0]) {
+                        return Ok(Self::one_of(&column, keys));
+                    }
+                }
+                Ok(Self::binary(&column, " = ", &value))
+            }
         }
     }
+    fn lt(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
+        self.compare(ident, value, " < ", |size, value| size < value)
+    }
+    fn gt(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
+        self.compare(ident, value, " > ", |size, value| size > value)
+    }
+    fn lt_eq(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
+        self.compare(ident, value, " <= ", |size, value| size <= value)
+    }
+    fn gt_eq(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
+        self.compare(ident, value, " >= ", |size, value| size >= value)
+    }
 
     fn between(
         &mut self,
@@ -283,53 +562,103 @@ impl compiler::Visitor<DynExpr, VisitorError> for SearchVisitor {
         high_value: Value,
     ) -> Result<DynExpr, VisitorError> {
         use diesel::dsl::*;
+        if let Some(path) = self.answer_path(&ident) {
+            if low_value.is_null() || high_value.is_null() {
+                return Ok(Box::new(sql::<Bool>("FALSE")));
+            }
+            return Ok(Self::any_value(
+                &path,
+                " BETWEEN ",
+                vec![low_value, high_value],
+            ));
+        }
         let column = verify_column(&ident).map_err(|c| VisitorError::InvalidColumn(c.into()))?;
 
         match (low_value, high_value) {
             (Value::Null, _) | (_, Value::Null) => Ok(Box::new(sql::<Bool>("FALSE"))),
-            (low_value, high_value) => Ok(if column.nullable {
-                Box::new(
-                    sql::<Bool>("ifnull(")
-                        .sql(column.column_name)
-                        .sql(" BETWEEN ")
-                        .sql(&to_sql_str(&low_value))
-                        .sql(" AND ")
-                        .sql(&to_sql_str(&high_value))
-                        .sql(", FALSE)"),
-                )
-            } else {
-                Box::new(
-                    sql::<Bool>(column.column_name)
-                        .sql(" BETWEEN ")
-                        .sql(&to_sql_str(&low_value))
-                        .sql(" AND ")
-                        .sql(&to_sql_str(&high_value)),
-                )
-            }),
+            (low_value, high_value) => {
+                if let Some(keys) =
+                    self.sized_keys(&column, &[&low_value, &high_value], |size, b| {
+                        b[0] <= size && size <= b[1]
+                    })
+                {
+                    return Ok(Self::one_of(&column, keys));
+                }
+                let low_value = self.resolve(&column, low_value);
+                let high_value = self.resolve(&column, high_value);
+                Ok(if column.nullable {
+                    Box::new(
+                        sql::<Bool>("ifnull(")
+                            .sql(column.expression)
+                            .sql(" BETWEEN ")
+                            .sql(&to_sql_str(&low_value))
+                            .sql(" AND ")
+                            .sql(&to_sql_str(&high_value))
+                            .sql(", FALSE)"),
+                    )
+                } else {
+                    Box::new(
+                        sql::<Bool>(column.expression)
+                            .sql(" BETWEEN ")
+                            .sql(&to_sql_str(&low_value))
+                            .sql(" AND ")
+                            .sql(&to_sql_str(&high_value)),
+                    )
+                })
+            }
         }
     }
 
     fn colon(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
         use diesel::dsl::*;
+        if let Some(path) = self.answer_path(&ident) {
+            if value.is_null() {
+                return Ok(Box::new(sql::<Bool>("FALSE")));
+            }
+            return Ok(Self::any_value(&path, " LIKE ", vec![value]));
+        }
         let column = verify_column(&ident).map_err(|c| VisitorError::InvalidColumn(c.into()))?;
 
         match value {
             Value::Null => Ok(Box::new(sql::<Bool>("FALSE"))),
-            value => Ok(if column.nullable {
-                Box::new(
-                    sql::<Bool>("ifnull(")
-                        .sql(column.column_name)
-                        .sql(" LIKE ")
-                        .sql(&to_sql_str(&value))
-                        .sql(", FALSE)"),
-                )
-            } else {
-                Box::new(
-                    sql::<Bool>(column.column_name)
-                        .sql(" LIKE ")
-                        .sql(&to_sql_str(&value)),
-                )
-            }),
+            value => {
+                let value = self.resolve(&column, value);
+                Ok(Self::binary(&column, " LIKE ", &value))
+            }
+        }
+    }
+
+    fn regex(&mut self, ident: String, value: Value) -> Result<DynExpr, VisitorError> {
+        use diesel::dsl::*;
+        if let Some(path) = self.answer_path(&ident) {
+            return match value {
+                Value::Null => Ok(Box::new(sql::<Bool>("FALSE"))),
+                Value::String(pattern) => {
+                    regex::Regex::new(&pattern)
+                        .map_err(|e| VisitorError::InvalidRegex(e.to_string()))?;
+                    Ok(Self::any_value(
+                        &path,
+                        " REGEXP ",
+                        vec![Value::String(pattern)],
+                    ))
+                }
+                other => Err(VisitorError::InvalidTypeUsedWithRegexOperator(
+                    json_type_name(&other),
+                )),
+            };
+        }
+        let column = verify_column(&ident).map_err(|c| VisitorError::InvalidColumn(c.into()))?;
+
+        match value {
+            Value::Null => Ok(Box::new(sql::<Bool>("FALSE"))),
+            Value::String(pattern) => {
+                regex::Regex::new(&pattern)
+                    .map_err(|e| VisitorError::InvalidRegex(e.to_string()))?;
+                Ok(Self::binary(&column, " REGEXP ", &Value::String(pattern)))
+            }
+            other => Err(VisitorError::InvalidTypeUsedWithRegexOperator(
+                json_type_name(&other),
+            )),
         }
     }
 
@@ -348,11 +677,13 @@ impl compiler::Visitor<DynExpr, VisitorError> for SearchVisitor {
 
 #[derive(FromForm, Debug)]
 pub(super) struct SearchForm<'f> {
-    limit: Option<i64>,
-    offset: Option<i64>,
-    search: Option<&'f str>,
-    order_table: Option<&'f str>,
-    ascending: Option<bool>,
+    pub(super) limit: Option<i64>,
+    pub(super) offset: Option<i64>,
+    pub(super) search: Option<&'f str>,
+    pub(super) order_table: Option<&'f str>,
+    pub(super) ascending: Option<bool>,
+    // comma separated columns to include, every column if left empty
+    pub(super) columns: Option<&'f str>,
 }
 
 macro_rules! dyn_qc_form_column {
@@ -457,6 +788,14 @@ macro_rules! dyn_qc_form_column {
                 let $ident = qc_forms::metadata;
                 $succ_text_optional
             }
+            "qc_answer_details" => {
+                let $ident = qc_forms::qc_answer_details;
+                $succ_text
+            }
+            "checklist_version" => {
+                let $ident = qc_forms::checklist_version;
+                $succ_text_optional
+            }
             _ => $fail,
         }
     };
@@ -465,25 +804,103 @@ macro_rules! dyn_qc_form_column {
 #[post("/search", data = "<search>")]
 pub(super) async fn search(
     db: Db,
+    config: &Config,
     search: Form<SearchForm<'_>>,
-) -> Result<Json<Vec<ExistingQCForm>>> {
-    let mut boxed = qc_forms::table.into_boxed();
+) -> Result<Json<projection::SearchResults>> {
+    let results = run_search(&db, config, &search).await?;
+    Ok(Json(projection::project(results, search.columns)?))
+}
 
-    let mut visitor = SearchVisitor::new();
-    'search: {
-        if let Some(search) = search.search {
-            if search.is_empty() || search.trim().is_empty() {
-                break 'search;
-            }
-            let res = ExpressionParser::new(search, &mut visitor).parse();
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub form: ExistingQCForm,
+    // the labels of every `;` separated sub query that matched this form
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+}
 
-            match res {
-                Ok(ok) => {
-                    boxed = boxed.filter(ok);
-                }
-                Err(err) => Err(err)?,
+pub(super) async fn run_search(
+    db: &Db,
+    config: &Config,
+    search: &SearchForm<'_>,
+) -> Result<Vec<SearchResult>> {
+    let boxed = build_query(config, search)?;
+    let mut filters = compile_filters(config, search.search)?;
+    if filters.len() < 2 {
+        filters.clear();
+    }
+    // each label is matched against the same page of results as a subquery, so no
+    // list of ids has to be bound
+    let labelled = filters
+        .into_iter()
+        .map(|(label, filter)| {
+            let shown = build_query(config, search)?.select(qc_forms::id);
+            Ok((label, filter, shown))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    rocket::debug!("{}", diesel::debug_query::<Sqlite, _>(&boxed));
+
+    db.run(move |conn| {
+        register_sql_functions(conn)?;
+        let qc_posts: Vec<ExistingQCForm> = boxed.load(conn)?;
+
+        let mut labels: HashMap<i32, Vec<String>> = HashMap::new();
+        for (label, filter, shown) in labelled {
+            let matched: Vec<i32> = qc_forms::table
+                .select(qc_forms::id)
+                .filter(filter)
+                .filter(qc_forms::id.eq_any(shown))
+                .load(conn)?;
+            for id in matched {
+                labels.entry(id).or_default().push(label.clone());
             }
         }
+
+        Ok(qc_posts
+            .into_iter()
+            .map(|form| SearchResult {
+                labels: labels.remove(&form.id).unwrap_or_default(),
+                form,
+            })
+            .collect())
+    })
+    .await
+}
+
+/// Compiles every `;` separated sub query of a search along with its label
+pub(super) fn compile_filters(
+    config: &Config,
+    search: Option<&str>,
+) -> Result<Vec<(String, DynExpr)>> {
+    let Some(search) = search else {
+        return Ok(Vec::new());
+    };
+    let mut visitor = SearchVisitor::new(config);
+    Ok(ExpressionParser::new(search, &mut visitor).parse_many()?)
+}
+
+/// Compiles a search expression into a filter, or `None` if there is nothing to search for.
+/// Sub queries are unioned together
+pub(super) fn compile_filter(config: &Config, search: Option<&str>) -> Result<Option<DynExpr>> {
+    Ok(compile_filters(config, search)?
+        .into_iter()
+        .map(|(_, filter)| filter)
+        .reduce(|ls, rs| Box::new(ls.or(rs))))
+}
+
+pub(super) fn build_query(
+    config: &Config,
+    search: &SearchForm<'_>,
+) -> Result<qc_forms::BoxedQuery<'static, Sqlite>> {
+    let mut boxed = match projection::parse_columns(search.columns)? {
+        Some(columns) => projection::select_columns(&columns),
+        None => qc_forms::table.into_boxed(),
+    };
+
+    if let Some(filter) = compile_filter(config, search.search)? {
+        boxed = boxed.filter(filter);
     }
     {
         let mut order_table = search.order_table.unwrap_or("id");
@@ -501,7 +918,14 @@ pub(super) async fn search(
                 {
                     boxed = boxed.order_by(col.asc());
                 },
-                {}
+                {
+                    // only derived columns have an expression to order by
+                    if column.is_derived() {
+                        boxed = boxed.order_by(
+                            diesel::dsl::sql::<diesel::sql_types::Text>(column.expression).asc(),
+                        );
+                    }
+                }
             );
         } else {
             dyn_qc_form_column!(
@@ -510,7 +934,14 @@ pub(super) async fn search(
                 {
                     boxed = boxed.order_by(col.desc());
                 },
-                {}
+                {
+                    // only derived columns have an expression to order by
+                    if column.is_derived() {
+                        boxed = boxed.order_by(
+                            diesel::dsl::sql::<diesel::sql_types::Text>(column.expression).desc(),
+                        );
+                    }
+                }
             );
         }
     }
@@ -523,12 +954,5 @@ pub(super) async fn search(
         }
     }
 
-    println!("{}", diesel::debug_query::<Sqlite, _>(&boxed));
-
-    let qc_posts: Vec<ExistingQCForm> = match db.run(move |conn| boxed.load(conn)).await {
-        Ok(ok) => ok,
-        Err(err) => Err(err)?,
-    };
-
-    Ok(qc_posts.into())
+    Ok(boxed)
 }
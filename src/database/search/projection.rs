@@ -0,0 +1,103 @@
+use diesel::dsl::sql;
+use diesel::sql_types::{Bool, Integer, Nullable, Text, TimestamptzSqlite};
+use diesel::sqlite::Sqlite;
+
+use rocket_sync_db_pools::diesel;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::*;
+
+/// Parses a comma separated list of columns, `None` meaning every column
+pub fn parse_columns(columns: Option<&str>) -> Result<Option<Vec<&'static str>>> {
+    let Some(columns) = columns else {
+        return Ok(None);
+    };
+    if columns.trim().is_empty() {
+        return Ok(None);
+    }
+    let mut parsed = vec!["id"];
+    for column in columns.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let column = verify_column(column).map_err(|c| DataBaseError::InvalidColumn(c.into()))?;
+        // derived columns are only there to search by
+        if column.is_derived() {
+            return Err(DataBaseError::InvalidColumn(column.column_name.into()));
+        }
+        if !parsed.contains(&column.column_name) {
+            parsed.push(column.column_name);
+        }
+    }
+    Ok(Some(parsed))
+}
+
+/// Selects the given columns while every other column is filled in with an empty placeholder
+/// so rows still load as an `ExistingQCForm`. Dates are cheap and have no valid empty
+/// value so they are always read
+pub fn select_columns(columns: &[&'static str]) -> qc_forms::BoxedQuery<'static, Sqlite> {
+    let pick = |column: &'static str, placeholder: &'static str| {
+        if columns.contains(&column) {
+            column
+        } else {
+            placeholder
+        }
+    };
+    qc_forms::table
+        .select((
+            sql::<Integer>("id"),
+            sql::<Bool>(pick("finalized", "FALSE")),
+            sql::<TimestamptzSqlite>("creation_date"),
+            sql::<TimestamptzSqlite>("last_updated"),
+            sql::<Text>(pick("build_location", "''")),
+            sql::<Text>(pick("build_type", "''")),
+            sql::<Text>(pick("drive_type", "''")),
+            sql::<Text>(pick("item_serial", "''")),
+            sql::<Nullable<Text>>(pick("asm_serial", "NULL")),
+            sql::<Text>(pick("oem_serial", "''")),
+            sql::<Text>(pick("make_model", "''")),
+            sql::<Bool>(pick("mso_installed", "FALSE")),
+            sql::<Text>(pick("operating_system", "''")),
+            sql::<Text>(pick("processor_gen", "''")),
+            sql::<Text>(pick("processor_type", "''")),
+            sql::<Text>(pick("qc_answers", "''")),
+            sql::<Text>(pick("qc1_initial", "''")),
+            sql::<Nullable<Text>>(pick("qc2_initial", "NULL")),
+            sql::<Text>(pick("ram_size", "''")),
+            sql::<Text>(pick("ram_type", "''")),
+            sql::<Nullable<Text>>(pick("sales_order", "NULL")),
+            sql::<Text>(pick("drive_size", "''")),
+            sql::<Text>(pick("tech_notes", "''")),
+            sql::<Nullable<Text>>(pick("metadata", "NULL")),
+            sql::<Text>(pick("qc_answer_details", "'{}'")),
+            sql::<Nullable<Integer>>(pick("checklist_version", "NULL")),
+        ))
+        .into_boxed()
+}
+
+/// Drops every field that wasn't asked for from the results
+pub fn sparse(results: Vec<SearchResult>, columns: &[&'static str]) -> Vec<Map<String, Value>> {
+    results
+        .into_iter()
+        .filter_map(|result| match serde_json::to_value(result) {
+            Ok(Value::Object(mut object)) => {
+                object.retain(|key, _| key == "labels" || columns.contains(&key.as_str()));
+                Some(object)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SearchResults {
+    Full(Vec<SearchResult>),
+    Sparse(Vec<Map<String, Value>>),
+}
+
+/// Keeps only the requested comma separated `columns` of the results, or all of them
+pub fn project(results: Vec<SearchResult>, columns: Option<&str>) -> Result<SearchResults> {
+    Ok(match parse_columns(columns)? {
+        Some(columns) => SearchResults::Sparse(sparse(results, &columns)),
+        None => SearchResults::Full(results),
+    })
+}
@@ -17,6 +17,8 @@ pub enum Token {
     And,
     Lt,
     Gt,
+    LtEq,
+    GtEq,
     Colon,
     Semicolon,
     Carrot,
@@ -41,6 +43,7 @@ impl TokenFull {
     }
 }
 
+#[derive(Clone)]
 pub struct Tokenizer<'a> {
     str: &'a str,
     chars: std::iter::Peekable<Chars<'a>>,
@@ -93,6 +96,9 @@ impl<'a> Iterator for Tokenizer<'a> {
             PathIndex,
             PathIdent,
 
+            Lt,
+            Gt,
+
             Number,
             NumberMinus,
             NumberLeadingZero,
@@ -100,6 +106,7 @@ impl<'a> Iterator for Tokenizer<'a> {
             NumberDotDig,
             NumberEPM,
             NumberED,
+            NumberUnit,
 
             JsonData {
                 obj: bool,
@@ -165,8 +172,8 @@ impl<'a> Iterator for Tokenizer<'a> {
                     Some(')') => ret = Some(Ok(Token::RPar)),
                     Some('|') => ret = Some(Ok(Token::Or)),
                     Some('&') => ret = Some(Ok(Token::And)),
-                    Some('>') => ret = Some(Ok(Token::Gt)),
-                    Some('<') => ret = Some(Ok(Token::Lt)),
+                    Some('>') => state = TokenizerState::Gt,
+                    Some('<') => state = TokenizerState::Lt,
                     Some('=') => ret = Some(Ok(Token::Eq)),
                     Some('*') => ret = Some(Ok(Token::Star)),
                     Some('^') => ret = Some(Ok(Token::Carrot)),
@@ -340,6 +347,20 @@ impl<'a> Iterator for Tokenizer<'a> {
                         ret = Some(Ok(token));
                     }
                 },
+                TokenizerState::Lt => match char {
+                    Some('=') => ret = Some(Ok(Token::LtEq)),
+                    _ => {
+                        consume_char = false;
+                        ret = Some(Ok(Token::Lt));
+                    }
+                },
+                TokenizerState::Gt => match char {
+                    Some('=') => ret = Some(Ok(Token::GtEq)),
+                    _ => {
+                        consume_char = false;
+                        ret = Some(Ok(Token::Gt));
+                    }
+                },
                 TokenizerState::NumberMinus => {
                     if let Some('0') = char {
                         state = TokenizerState::NumberLeadingZero;
@@ -355,6 +376,7 @@ impl<'a> Iterator for Tokenizer<'a> {
                     )),
                     Some('e' | 'E') => state = TokenizerState::NumberEPM,
                     Some('.') => state = TokenizerState::NumberDot,
+                    Some(char) if char.is_alphabetic() => state = TokenizerState::NumberUnit,
                     _ => {
                         consume_char = false;
                         ret = Some(Ok(Token::Value(Value::Number(0.into()))));
@@ -364,6 +386,7 @@ impl<'a> Iterator for Tokenizer<'a> {
                     Some('0'..='9') => state = TokenizerState::Number,
                     Some('e' | 'E') => state = TokenizerState::NumberEPM,
                     Some('.') => state = TokenizerState::NumberDot,
+                    Some(char) if char.is_alphabetic() => state = TokenizerState::NumberUnit,
                     _ => {
                         consume_char = false;
                         match Number::from_str(
@@ -391,6 +414,7 @@ impl<'a> Iterator for Tokenizer<'a> {
                 TokenizerState::NumberDotDig => match char {
                     Some('0'..='9') => state = TokenizerState::NumberDotDig,
                     Some('e' | 'E') => state = TokenizerState::NumberEPM,
+                    Some(char) if char.is_alphabetic() => state = TokenizerState::NumberUnit,
                     _ => {
                         consume_char = false;
                         match Number::from_str(
@@ -408,9 +432,11 @@ impl<'a> Iterator for Tokenizer<'a> {
                 },
                 TokenizerState::NumberEPM => match char {
                     Some('+' | '-' | '0'..='9') => state = TokenizerState::NumberED,
-                    _ => error_inclusive!(TokenizerError::InvalidNumber(
-                        "invalid char, expected + or - or 0-9".into()
-                    )),
+                    // not an exponent after all but a unit starting with e, like 16EB
+                    _ => {
+                        consume_char = false;
+                        state = TokenizerState::NumberUnit;
+                    }
                 },
                 TokenizerState::NumberED => match char {
                     Some('0'..='9') => state = TokenizerState::NumberED,
@@ -429,6 +455,17 @@ impl<'a> Iterator for Tokenizer<'a> {
                         }
                     }
                 },
+                // numbers directly followed by a unit like 16GiB are kept as strings
+                TokenizerState::NumberUnit => match char {
+                    Some(char) if char.is_alphabetic() => {}
+                    _ => {
+                        consume_char = false;
+                        let token = Token::Value(Value::String(
+                            self.str[self.current.byte_index..current.byte_index].to_owned(),
+                        ));
+                        ret = Some(Ok(token));
+                    }
+                },
 
                 TokenizerState::JsonData { obj, indent } => match (obj, char) {
                     (true, Some('}')) | (false, Some(']')) => {
@@ -552,3 +589,50 @@ fn toknizer_test() {
         }
     }
 }
+
+#[test]
+fn test_number_units() {
+    let tokens = |str| {
+        Tokenizer::new(str)
+            .map(|token| token.map(|token| token.data))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    };
+
+    // a unit right after a number makes the whole literal a string, the visitor
+    // turns it into bytes for sized columns and compares it as text otherwise
+    assert_eq!(
+        tokens("ram_size >= 16GiB"),
+        [
+            Token::Ident("ram_size".into()),
+            Token::GtEq,
+            Token::Value(Value::String("16GiB".into())),
+        ]
+    );
+    assert_eq!(
+        tokens("1.5TB 0kb"),
+        [
+            Token::Value(Value::String("1.5TB".into())),
+            Token::Value(Value::String("0kb".into())),
+        ]
+    );
+    // separated by a space the unit is an identifier of its own
+    assert_eq!(
+        tokens("16 GiB"),
+        [
+            Token::Value(Value::Number(16.into())),
+            Token::Ident("GiB".into())
+        ]
+    );
+    // units can start with an e as long as no exponent follows it
+    assert_eq!(
+        tokens("16EB 1E"),
+        [
+            Token::Value(Value::String("16EB".into())),
+            Token::Value(Value::String("1E".into())),
+        ]
+    );
+    // exponents are still numbers
+    assert_eq!(tokens("2e3"), [Token::Value(serde_json::json!(2e3))]);
+    assert_eq!(tokens("2E-1"), [Token::Value(serde_json::json!(2e-1))]);
+}
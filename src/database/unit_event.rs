@@ -0,0 +1,213 @@
+use rocket::response::status::Created;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+
+use rocket_sync_db_pools::diesel;
+
+use diesel::{
+    backend::Backend,
+    deserialize::{FromSql, FromSqlRow},
+    expression::AsExpression,
+    serialize::ToSql,
+    sql_types::Text,
+    sqlite::Sqlite,
+};
+
+use crate::time::Time;
+
+use self::diesel::prelude::*;
+
+use super::*;
+
+/// The search columns worked out from the events of each form, see `search::verify_column`.
+/// Status, date and location come from the latest event, the carrier and tracking number
+/// from the latest shipment
+pub const UNIT_STATUS: &str =
+    "(SELECT event FROM unit_events WHERE form_id = qc_forms.id ORDER BY id DESC LIMIT 1)";
+pub const UNIT_STATUS_DATE: &str =
+    "(SELECT event_date FROM unit_events WHERE form_id = qc_forms.id ORDER BY id DESC LIMIT 1)";
+pub const UNIT_LOCATION: &str =
+    "(SELECT location FROM unit_events WHERE form_id = qc_forms.id ORDER BY id DESC LIMIT 1)";
+pub const CARRIER: &str = "(SELECT carrier FROM unit_events WHERE form_id = qc_forms.id AND event = 'shipped' ORDER BY id DESC LIMIT 1)";
+pub const TRACKING_NUMBER: &str = "(SELECT tracking_number FROM unit_events WHERE form_id = qc_forms.id AND event = 'shipped' ORDER BY id DESC LIMIT 1)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub enum UnitEventKind {
+    /// put away at `location`
+    Shelved,
+    /// sent out with `carrier` under `tracking_number`
+    Shipped,
+    /// came back from a shipment, `location` is where it was received if known
+    Returned,
+}
+
+impl UnitEventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Shelved => "shelved",
+            Self::Shipped => "shipped",
+            Self::Returned => "returned",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for UnitEventKind {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Sqlite>,
+    ) -> diesel::serialize::Result {
+        out.set_value(self.as_str());
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for UnitEventKind {
+    fn from_sql(bytes: <Sqlite as Backend>::RawValue<'_>) -> diesel::deserialize::Result<Self> {
+        let val = <String as diesel::deserialize::FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(serde_json::from_value(serde_json::Value::String(val))?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+#[serde(crate = "rocket::serde")]
+pub struct UnitEvent {
+    pub id: i32,
+    pub form_id: i32,
+    pub event: UnitEventKind,
+    // example Shelf B4
+    pub location: Option<String>,
+    pub carrier: Option<String>,
+    pub tracking_number: Option<String>,
+    pub notes: String,
+    // initials of whoever recorded it
+    pub recorded_by: String,
+    pub event_date: Time,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name = super::schema::unit_events)]
+pub struct NewUnitEvent {
+    #[serde(skip_deserializing)]
+    pub form_id: i32,
+    pub event: UnitEventKind,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub carrier: Option<String>,
+    #[serde(default)]
+    pub tracking_number: Option<String>,
+    #[serde(default)]
+    pub notes: String,
+    pub recorded_by: String,
+    #[serde(skip_deserializing)]
+    #[serde(default = "time_default")]
+    pub event_date: Time,
+}
+
+fn given(field: &Option<String>) -> bool {
+    field
+        .as_deref()
+        .is_some_and(|field| !field.trim().is_empty())
+}
+
+/// Whether `event` can follow the `latest` event of a unit, a shipped unit has to come
+/// back before it can be shelved or shipped again
+fn check_event(latest: Option<UnitEventKind>, event: &NewUnitEvent) -> Result<(), String> {
+    use UnitEventKind::*;
+    if event.recorded_by.trim().is_empty() {
+        return Err("who recorded the event is required".into());
+    }
+    match event.event {
+        Shelved if !given(&event.location) => Err("a shelved unit needs a location".into()),
+        Shipped if !given(&event.carrier) || !given(&event.tracking_number) => {
+            Err("a shipment needs a carrier and tracking number".into())
+        }
+        Shelved | Shipped if latest == Some(Shipped) => {
+            Err("the unit has been shipped and has to be returned first".into())
+        }
+        Returned if latest != Some(Shipped) => Err("only a shipped unit can be returned".into()),
+        _ => Ok(()),
+    }
+}
+
+/// Every event of a form, oldest first
+#[get("/unit_events/<id>")]
+pub(super) async fn list_unit_events(db: Db, id: i32) -> Result<Json<Vec<UnitEvent>>> {
+    db.run(move |conn| {
+        Ok(Json(
+            unit_events::table
+                .filter(unit_events::form_id.eq(id))
+                .order(unit_events::id.asc())
+                .load(conn)?,
+        ))
+    })
+    .await
+}
+
+/// Events are only recorded for finalized forms and leave the form as it is, so nothing
+/// has to be definalized to track a unit
+#[post("/unit_events/<id>", data = "<event>")]
+pub(super) async fn new_unit_event(
+    db: Db,
+    id: i32,
+    mut event: Json<NewUnitEvent>,
+) -> Result<Created<Json<UnitEvent>>> {
+    event.form_id = id;
+    let event: UnitEvent = db
+        .run(move |conn| {
+            conn.transaction(|conn| {
+                let finalized: bool = qc_forms::table
+                    .find(id)
+                    .select(qc_forms::finalized)
+                    .get_result(conn)?;
+                if !finalized {
+                    return Err(DataBaseError::UnitNotFinalized);
+                }
+                let latest: Option<UnitEventKind> = unit_events::table
+                    .filter(unit_events::form_id.eq(id))
+                    .order(unit_events::id.desc())
+                    .select(unit_events::event)
+                    .first(conn)
+                    .optional()?;
+                check_event(latest, &event).map_err(DataBaseError::InvalidUnitEvent)?;
+
+                diesel::insert_into(unit_events::table)
+                    .values(&*event)
+                    .execute(conn)?;
+                Ok(unit_events::table
+                    .order(unit_events::id.desc())
+                    .first(conn)?)
+            })
+        })
+        .await?;
+    Ok(Created::new("/").body(Json(event)))
+}
+
+#[test]
+fn test_check_event() {
+    use UnitEventKind::*;
+    let event = |json| serde_json::from_value::<NewUnitEvent>(json).unwrap();
+    let shelve =
+        event(serde_json::json!({"event": "shelved", "location": "B4", "recorded_by": "JD"}));
+    let ship = event(serde_json::json!({
+        "event": "shipped", "carrier": "UPS", "tracking_number": "1Z999", "recorded_by": "JD"
+    }));
+    let ret = event(serde_json::json!({"event": "returned", "recorded_by": "JD"}));
+
+    assert!(check_event(None, &shelve).is_ok());
+    assert!(check_event(Some(Shelved), &ship).is_ok());
+    assert!(check_event(Some(Shipped), &ret).is_ok());
+    assert!(check_event(Some(Returned), &shelve).is_ok());
+
+    assert!(check_event(Some(Shipped), &shelve).is_err());
+    assert!(check_event(Some(Shipped), &ship).is_err());
+    assert!(check_event(Some(Shelved), &ret).is_err());
+    let mut untracked = ship.clone();
+    untracked.tracking_number = Some(" ".into());
+    assert!(check_event(None, &untracked).is_err());
+    let mut anonymous = shelve.clone();
+    anonymous.recorded_by = String::new();
+    assert!(check_event(None, &anonymous).is_err());
+}
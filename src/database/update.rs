@@ -6,9 +6,10 @@ use rocket::serde::{json::Json, Deserialize, Serialize};
 
 use rocket_sync_db_pools::diesel;
 
-use crate::qc_checklist::QCChecklist;
+use crate::qc_checklist::{QCAnswerDetails, QCChecklist};
 
 use crate::time::Time;
+use crate::SharedConfig;
 
 use self::diesel::prelude::*;
 
@@ -52,9 +53,12 @@ pub struct QCFormUpdate {
     #[serde(deserialize_with = "deserialize_optional_field")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<Option<JsonText>>,
+    pub qc_answer_details: Option<QCAnswerDetails>,
 }
 
-fn deserialize_optional_field<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+pub(super) fn deserialize_optional_field<'de, T, D>(
+    deserializer: D,
+) -> Result<Option<Option<T>>, D::Error>
 where
     D: serde::Deserializer<'de>,
     T: Deserialize<'de>,
@@ -65,11 +69,13 @@ where
 #[post("/update_post/<id>", data = "<update>")]
 pub(super) async fn update_post(
     db: Db,
+    config: SharedConfig,
     id: i32,
     mut update: Json<QCFormUpdate>,
-) -> Result<Accepted<Json<ExistingQCForm>>> {
+) -> Result<Accepted<Json<SummarizedQCForm>>> {
     update.last_updated = Some(time_default());
-    let res: ExistingQCForm = db
+    // the answers are checked against the build type and checklist stored with the form
+    let res = db
         .run(move |conn| {
             let finalized: bool = qc_forms::table
                 .find(id)
@@ -80,25 +86,121 @@ pub(super) async fn update_post(
                 return Err(DataBaseError::UpdatedFinalized);
             }
 
+            let location = match &update.build_location {
+                Some(location) => location.clone(),
+                None => qc_forms::table
+                    .find(id)
+                    .select(qc_forms::build_location)
+                    .get_result(conn)?,
+            };
+            create::check_patterns(
+                config.for_location(&location),
+                &serde_json::to_value(&*update).unwrap_or_default(),
+            )?;
+
+            if let Some(make_model) = &update.make_model {
+                update.make_model = Some(device_model::normalize(conn, make_model)?);
+            }
+
+            let answered = update.qc_answers.is_some() || update.qc_answer_details.is_some();
+            if answered || update.build_type.is_some() || update.build_location.is_some() {
+                let existing: ExistingQCForm = qc_forms::table.find(id).first(conn)?;
+                let config = checklist::pinned(
+                    conn,
+                    &config,
+                    update
+                        .build_location
+                        .as_ref()
+                        .unwrap_or(&existing.build_location),
+                    existing.checklist_version,
+                )?;
+                // the build type sets how many passes the answers need
+                let build_type = update.build_type.as_ref().unwrap_or(&existing.build_type);
+                update
+                    .qc_answers
+                    .as_ref()
+                    .unwrap_or(&existing.qc_answers)
+                    .check_passes(&config, build_type)?;
+
+                if answered {
+                    let mut answers = update
+                        .qc_answers
+                        .clone()
+                        .unwrap_or_else(|| existing.qc_answers.clone());
+                    answers.grade(
+                        &config,
+                        &update.qc_answer_details.clone().unwrap_or_default(),
+                        Some(&existing.qc_answer_details),
+                    )?;
+                    let initials = [
+                        Some(
+                            update
+                                .qc1_initial
+                                .as_ref()
+                                .unwrap_or(&existing.qc1_initial)
+                                .as_str(),
+                        ),
+                        match &update.qc2_initial {
+                            Some(qc2_initial) => qc2_initial.as_deref(),
+                            None => existing.qc2_initial.as_deref(),
+                        },
+                    ];
+                    // passes without details are carried over from the existing ones by `sync`
+                    let mut details = update.qc_answer_details.clone().unwrap_or_default();
+                    details.sync(
+                        Some(&existing.qc_answer_details),
+                        &answers,
+                        initials,
+                        time_default(),
+                    );
+                    update.qc_answers = Some(answers);
+                    update.qc_answer_details = Some(details);
+                }
+            }
+
+            let moved_from: Option<String> = match &update.sales_order {
+                Some(_) => qc_forms::table
+                    .find(id)
+                    .select(qc_forms::sales_order)
+                    .get_result(conn)?,
+                None => None,
+            };
+
             diesel::update(qc_forms::table.filter(qc_forms::id.eq(id)))
                 .set(&*update)
                 .execute(conn)?;
-            Ok(qc_forms::table.filter(qc_forms::id.eq(id)).first(conn)?)
+            let form: ExistingQCForm = qc_forms::table.filter(qc_forms::id.eq(id)).first(conn)?;
+            if moved_from != form.sales_order {
+                sales_order::refresh(conn, moved_from.as_deref())?;
+            }
+            sales_order::refresh(conn, form.sales_order.as_deref())?;
+            SummarizedQCForm::load(conn, &config, form)
         })
         .await?;
     Ok(Accepted(Json(res)))
 }
 
 #[post("/finalize_post/<id>")]
-pub(super) async fn finalize_post(db: Db, id: i32) -> Result<Json<ExistingQCForm>> {
+pub(super) async fn finalize_post(
+    db: Db,
+    config: SharedConfig,
+    id: i32,
+) -> Result<Json<SummarizedQCForm>> {
     db.run(move |conn| {
-        diesel::update(qc_forms::table.find(id))
-            .set(qc_forms::finalized.eq(true))
-            .execute(conn)?;
-        Ok(qc_forms::table
-            .find(id)
-            .get_result::<ExistingQCForm>(conn)?
-            .into())
+        let form = finalize(conn, id)?;
+        Ok(SummarizedQCForm::load(conn, &config, form)?.into())
     })
     .await
 }
+
+/// Finalizes the form and moves the sales order it belongs to along
+pub(super) fn finalize(conn: &mut diesel::SqliteConnection, id: i32) -> Result<ExistingQCForm> {
+    diesel::update(qc_forms::table.find(id))
+        .set(qc_forms::finalized.eq(true))
+        .execute(conn)?;
+    let form = qc_forms::table
+        .find(id)
+        .get_result::<ExistingQCForm>(conn)?;
+    sales_order::refresh(conn, form.sales_order.as_deref())?;
+    Ok(form)
+}
@@ -1,10 +1,7 @@
-use std::path::Path;
-
 use rocket::{
     fairing::AdHoc,
     figment::value::magic::RelativePathBuf,
     fs::{FileServer, Options},
-    request::FromRequest,
 };
 
 #[macro_use]
@@ -15,6 +12,7 @@ extern crate rocket_sync_db_pools;
 use rocket_dyn_templates::Template;
 
 pub mod admin_pwd;
+pub mod config;
 pub mod database;
 pub mod json_text;
 pub mod qc_checklist;
@@ -24,36 +22,7 @@ pub mod time;
 
 pub mod copy_session;
 
-#[derive(Debug)]
-pub struct Config(pub serde_json::Value);
-
-impl Config {
-    fn load_from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
-        let contents = std::fs::read_to_string(path)?;
-        Ok(Self(json5::from_str(&contents)?))
-    }
-}
-
-#[derive(Debug)]
-pub struct FailedToObtainConfig;
-
-#[rocket::async_trait]
-impl<'r> FromRequest<'r> for &'r Config {
-    type Error = FailedToObtainConfig;
-
-    async fn from_request(
-        request: &'r rocket::Request<'_>,
-    ) -> rocket::request::Outcome<Self, Self::Error> {
-        if let Some(config) = request.rocket().state::<Config>() {
-            rocket::request::Outcome::Success(config)
-        } else {
-            rocket::outcome::Outcome::Error((
-                rocket::http::Status::InternalServerError,
-                FailedToObtainConfig,
-            ))
-        }
-    }
-}
+pub use config::{Config, LiveConfig, SharedConfig};
 
 mod helper {
     use rocket_dyn_templates::handlebars::{
@@ -85,40 +54,7 @@ mod helper {
 fn rocket() -> _ {
     rocket::build()
         .attach(admin_pwd::stage())
-        .attach(AdHoc::try_on_ignite("Config", |rocket| async {
-            let path = rocket
-                .figment()
-                .extract_inner::<RelativePathBuf>("config")
-                .map(|p| p.relative());
-
-            let path = match path {
-                Ok(dir) => dir,
-                Err(e) => {
-                    rocket::config::pretty_print_error(e);
-                    return Err(rocket);
-                }
-            };
-
-            if path.exists() && path.is_file() {
-                match Config::load_from_file(&path) {
-                    Ok(ok) => Ok(rocket.manage(ok)),
-                    Err(err) => {
-                        rocket::error!(
-                            "Provided config '{}' is malformed\n{err:?}",
-                            path.display()
-                        );
-                        panic!();
-                    }
-                }
-            } else {
-                if path.exists() {
-                    rocket::error!("Provided config path '{}' is not a file", path.display());
-                } else {
-                    rocket::error!("Provided config path '{}' does not exist", path.display());
-                }
-                Err(rocket)
-            }
-        }))
+        .attach(config::stage())
         .attach(AdHoc::try_on_ignite("Scripting", |rocket| async {
             let path = rocket
                 .figment()
@@ -183,4 +119,4 @@ fn rocket() -> _ {
             )
             .rank(5),
         )
-}
\ No newline at end of file
+}
@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 use ::serde::{Deserialize, Serialize};
@@ -10,6 +11,17 @@ use diesel::{
     sqlite::Sqlite,
 };
 use serde::de::Visitor;
+use serde_json::Value;
+
+use crate::config::{Question, QuestionKind};
+use crate::database::search::compiler::{self, ExpressionParser};
+use crate::database::search::{aliases, json_type_name, verify_column, ColumnInfo, VisitorError};
+use crate::database::DataBaseError;
+use crate::time::Time;
+use crate::Config;
+
+/// Passes per question when neither the question nor the build type say otherwise
+pub const DEFAULT_PASSES: usize = 2;
 
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
 pub enum QuestionAnswer {
@@ -19,19 +31,33 @@ pub enum QuestionAnswer {
     NA,
 }
 
-#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
-pub struct QuestionAnswers(pub [QuestionAnswer; 2]);
+/// One answer per pass, encoded as one character each. Forms saved before the
+/// pass count was configurable always have two
+#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+pub struct QuestionAnswers(pub Vec<QuestionAnswer>);
+
+impl QuestionAnswers {
+    pub fn parse(val: &str) -> Result<Self, QuestionAnswerError> {
+        if val.is_empty() {
+            return Err(QuestionAnswerError::NoAnswers);
+        }
+        val.chars()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
 
-impl QuestionAnswers {}
+    pub fn encode(&self) -> String {
+        self.0.iter().map(QuestionAnswer::as_char).collect()
+    }
+}
 
 impl Serialize for QuestionAnswers {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let bytes = [self.0[0].as_char() as u8, self.0[1].as_char() as u8];
-        let str = std::str::from_utf8(&bytes).unwrap();
-        serializer.serialize_str(str)
+        serializer.serialize_str(&self.encode())
     }
 }
 
@@ -41,25 +67,14 @@ impl<'de> Visitor<'de> for QuestionsVisitor {
     type Value = QuestionAnswers;
 
     fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "expecting string like [pfniPFNI]{{2}}")
+        write!(f, "expecting string like [pfniPFNI]+")
     }
 
     fn visit_str<E>(self, val: &str) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        let b = val.as_bytes();
-        if b.len() != 2 {
-            return Err(serde::de::Error::custom("Invalid answer length"));
-        }
-        let qca1 = b[0] as char;
-        let qca2 = b[1] as char;
-        Ok(QuestionAnswers([
-            qca1.try_into()
-                .map_err(|e| serde::de::Error::custom(format!("{}", e)))?,
-            qca2.try_into()
-                .map_err(|e| serde::de::Error::custom(format!("{}", e)))?,
-        ]))
+        QuestionAnswers::parse(val).map_err(|e| serde::de::Error::custom(format!("{}", e)))
     }
 }
 
@@ -75,6 +90,7 @@ impl<'de> Deserialize<'de> for QuestionAnswers {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum QuestionAnswerError {
     InvalidChar(char),
+    NoAnswers,
 }
 impl std::fmt::Display for QuestionAnswerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -124,6 +140,21 @@ impl<'de> Visitor<'de> for QuestionVisitor {
         v.try_into()
             .map_err(|e| serde::de::Error::custom(format!("{}", e)))
     }
+
+    // self describing formats like json hand chars over as strings
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        let mut chars = v.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => self.visit_char(c),
+            _ => Err(serde::de::Error::invalid_value(
+                serde::de::Unexpected::Str(v),
+                &self,
+            )),
+        }
+    }
 }
 
 impl<'de> Deserialize<'de> for QuestionAnswer {
@@ -153,6 +184,364 @@ impl QCChecklist {
     pub fn new() -> Self {
         Self(HashMap::new())
     }
+
+    /// Every question needs exactly as many answers as the config gives it passes
+    pub fn check_passes(&self, config: &Config, build_type: &str) -> Result<(), DataBaseError> {
+        for (question, answers) in &self.0 {
+            let expected = pass_count(config, build_type, question);
+            if answers.0.len() != expected {
+                return Err(DataBaseError::InvalidPassCount {
+                    question: question.clone(),
+                    expected,
+                    found: answers.0.len(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Decides pass or fail for the answered passes of typed questions from their values,
+    /// taken from `details` or else from `previous`. Incomplete and N/A passes need no value
+    pub fn grade(
+        &mut self,
+        config: &Config,
+        details: &QCAnswerDetails,
+        previous: Option<&QCAnswerDetails>,
+    ) -> Result<(), DataBaseError> {
+        for (question, answers) in self.0.iter_mut() {
+            let Some(typed) = config
+                .question(question)
+                .filter(|typed| typed.kind != QuestionKind::Check)
+            else {
+                continue;
+            };
+            let values = details
+                .0
+                .get(question)
+                .or_else(|| previous.and_then(|p| p.0.get(question)));
+            for (pass, answer) in answers.0.iter_mut().enumerate() {
+                if matches!(answer, QuestionAnswer::Incomplete | QuestionAnswer::NA) {
+                    continue;
+                }
+                let value = values
+                    .and_then(|v| v.get(pass))
+                    .and_then(|d| d.value.as_ref())
+                    .ok_or_else(|| DataBaseError::MissingAnswerValue(question.clone()))?;
+                *answer =
+                    typed
+                        .grade(value)
+                        .map_err(|reason| DataBaseError::InvalidAnswerValue {
+                            question: question.clone(),
+                            reason,
+                        })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Question {
+    pub const DEFAULT_TEXT_LENGTH: usize = 128;
+
+    /// The answer a value gives, or why it isn't a valid value for this question's `type`
+    pub fn grade(&self, value: &Value) -> Result<QuestionAnswer, String> {
+        let pass_if = |pass: bool| {
+            if pass {
+                QuestionAnswer::Pass
+            } else {
+                QuestionAnswer::Fail
+            }
+        };
+        match self.kind {
+            QuestionKind::Check => Err("question takes no value".into()),
+            QuestionKind::Number => {
+                let value = value.as_f64().ok_or("expected a number")?;
+                Ok(pass_if(
+                    self.min.is_none_or(|min| min <= value)
+                        && self.max.is_none_or(|max| value <= max),
+                ))
+            }
+            QuestionKind::Choice => {
+                let value = value.as_str().ok_or("expected a choice")?;
+                let choice = self
+                    .choices
+                    .as_ref()
+                    .and_then(|choices| choices.values.get(value))
+                    .ok_or_else(|| format!("'{value}' is not one of the choices"))?;
+                Ok(pass_if(!choice.fails))
+            }
+            QuestionKind::Text => {
+                let value = value.as_str().ok_or("expected text")?;
+                if value.trim().is_empty() {
+                    return Err("expected text".into());
+                }
+                let max_length = self.max_length.unwrap_or(Self::DEFAULT_TEXT_LENGTH);
+                if value.chars().count() > max_length {
+                    return Err(format!("longer than {max_length} characters"));
+                }
+                Ok(QuestionAnswer::Pass)
+            }
+        }
+    }
+}
+
+/// How many times `question` is checked on a form of `build_type`. Set with `passes`
+/// on the question, then on the build type and then on `qc_checks` itself
+pub fn pass_count(config: &Config, build_type: &str, question: &str) -> usize {
+    config
+        .question(question)
+        .and_then(|question| question.passes)
+        .or_else(|| {
+            config
+                .enumeration("build_types")?
+                .values
+                .get(build_type)?
+                .passes
+        })
+        .or_else(|| config.typed()?.qc_checks.passes)
+        .unwrap_or(DEFAULT_PASSES)
+}
+
+/// Whether `question` is asked on a form with the given fields. Questions can limit
+/// themselves to `whitelist_build_types` and to forms matching `applies_when`, a search
+/// expression over the form like `drive_type = "HDD" & !operating_system = "linux"`
+pub fn applies(config: &Config, question: &str, form: &Value) -> Result<bool, DataBaseError> {
+    let Some(question) = config.question(question) else {
+        return Ok(true);
+    };
+    if question.hidden {
+        return Ok(false);
+    }
+    if let Some(whitelist) = &question.whitelist_build_types {
+        if !whitelist
+            .iter()
+            .any(|build_type| form["build_type"] == **build_type)
+        {
+            return Ok(false);
+        }
+    }
+    let Some(rule) = &question.applies_when else {
+        return Ok(true);
+    };
+    let mut visitor = RuleVisitor { config, form };
+    // every `;` separated part of the rule has to hold
+    Ok(ExpressionParser::new(rule, &mut visitor)
+        .parse_many()?
+        .into_iter()
+        .all(|(_, applies)| applies))
+}
+
+/// Every question asked on a form with the given fields, in the order of `tech_form`
+pub fn applicable_questions(config: &Config, form: &Value) -> Result<Vec<String>, DataBaseError> {
+    let mut applicable = Vec::new();
+    for question in config
+        .tech_form()
+        .iter()
+        .flat_map(|section| section.questions.iter())
+    {
+        if applies(config, question, form)? {
+            applicable.push(question.clone());
+        }
+    }
+    Ok(applicable)
+}
+
+/// How many answers of one pass are in each state
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PassSummary {
+    pub pass: usize,
+    pub fail: usize,
+    pub na: usize,
+    pub incomplete: usize,
+}
+
+/// Where a form stands on the questions that apply to it
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ChecklistSummary {
+    pub applicable: usize,
+    // one for every pass, questions with fewer passes only count towards their own
+    pub passes: Vec<PassSummary>,
+    pub failing: Vec<String>,
+    // applicable questions the form has no answers for, they count as incomplete
+    pub unanswered: Vec<String>,
+    pub ready_to_finalize: bool,
+}
+
+impl QCChecklist {
+    /// Sums up the answers to the questions that apply to `form`, answers to questions
+    /// that don't apply are left out
+    pub fn summary(
+        &self,
+        config: &Config,
+        form: &Value,
+    ) -> Result<ChecklistSummary, DataBaseError> {
+        let build_type = form["build_type"].as_str().unwrap_or_default();
+        let mut summary = ChecklistSummary::default();
+
+        for question in applicable_questions(config, form)? {
+            summary.applicable += 1;
+            let passes = pass_count(config, build_type, &question);
+            if summary.passes.len() < passes {
+                summary.passes.resize_with(passes, Default::default);
+            }
+            let answers = match self.0.get(&question) {
+                Some(answers) => &answers.0[..],
+                None => {
+                    summary.unanswered.push(question.clone());
+                    &[]
+                }
+            };
+            for (pass, counts) in summary.passes.iter_mut().enumerate().take(passes) {
+                match answers
+                    .get(pass)
+                    .copied()
+                    .unwrap_or(QuestionAnswer::Incomplete)
+                {
+                    QuestionAnswer::Pass => counts.pass += 1,
+                    QuestionAnswer::Fail => counts.fail += 1,
+                    QuestionAnswer::NA => counts.na += 1,
+                    QuestionAnswer::Incomplete => counts.incomplete += 1,
+                }
+            }
+            if answers.contains(&QuestionAnswer::Fail) {
+                summary.failing.push(question);
+            }
+        }
+
+        summary.ready_to_finalize =
+            summary.failing.is_empty() && summary.passes.iter().all(|pass| pass.incomplete == 0);
+        Ok(summary)
+    }
+}
+
+/// Evaluates search expressions against the fields of a single form instead of the database
+struct RuleVisitor<'a> {
+    config: &'a Config,
+    form: &'a Value,
+}
+
+impl<'a> RuleVisitor<'a> {
+    fn field(&self, ident: &str) -> Result<(ColumnInfo, &'a Value), VisitorError> {
+        let column = verify_column(ident).map_err(|c| VisitorError::InvalidColumn(c.into()))?;
+        let field = &self.form[column.column_name];
+        Ok((column, field))
+    }
+
+    /// Maps display names onto keys like the search does
+    fn resolve(&self, column: &ColumnInfo, value: Value) -> Value {
+        match aliases::enumeration(column.column_name) {
+            Some(enumeration) => aliases::resolve_value(self.config, enumeration, value),
+            None => value,
+        }
+    }
+
+    fn order(&self, column: &ColumnInfo, field: &Value, value: Value) -> Option<Ordering> {
+        if aliases::is_sized(column.column_name) {
+            let enumeration = aliases::enumeration(column.column_name)?;
+            let size = |v| aliases::size_bytes(self.config, enumeration, v);
+            return Some(size(field)?.cmp(&size(&value)?));
+        }
+        match (field, self.resolve(column, value)) {
+            (Value::Number(field), Value::Number(value)) => {
+                field.as_f64()?.partial_cmp(&value.as_f64()?)
+            }
+            (Value::String(field), Value::String(value)) => Some(field.as_str().cmp(&value)),
+            _ => None,
+        }
+    }
+
+    fn compare(
+        &self,
+        ident: String,
+        value: Value,
+        pred: impl Fn(Ordering) -> bool,
+    ) -> Result<bool, VisitorError> {
+        let (column, field) = self.field(&ident)?;
+        Ok(self.order(&column, field, value).is_some_and(pred))
+    }
+
+    fn text(field: &Value) -> Option<String> {
+        match field {
+            Value::String(str) => Some(str.clone()),
+            Value::Number(num) => Some(num.to_string()),
+            Value::Bool(bool) => Some(bool.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> compiler::Visitor<bool, VisitorError> for RuleVisitor<'a> {
+    fn eq(&mut self, ident: String, value: Value) -> Result<bool, VisitorError> {
+        let (column, field) = self.field(&ident)?;
+        if value.is_null() {
+            return Ok(field.is_null());
+        }
+        let value = self.resolve(&column, value);
+        Ok(*field == value || self.order(&column, field, value) == Some(Ordering::Equal))
+    }
+    fn lt(&mut self, ident: String, value: Value) -> Result<bool, VisitorError> {
+        self.compare(ident, value, Ordering::is_lt)
+    }
+    fn gt(&mut self, ident: String, value: Value) -> Result<bool, VisitorError> {
+        self.compare(ident, value, Ordering::is_gt)
+    }
+    fn lt_eq(&mut self, ident: String, value: Value) -> Result<bool, VisitorError> {
+        self.compare(ident, value, Ordering::is_le)
+    }
+    fn gt_eq(&mut self, ident: String, value: Value) -> Result<bool, VisitorError> {
+        self.compare(ident, value, Ordering::is_ge)
+    }
+    fn between(
+        &mut self,
+        low_value: Value,
+        ident: String,
+        high_value: Value,
+    ) -> Result<bool, VisitorError> {
+        Ok(self.compare(ident.clone(), low_value, Ordering::is_ge)?
+            && self.compare(ident, high_value, Ordering::is_le)?)
+    }
+
+    /// Like sqlites `LIKE`, `%` and `_` are wildcards and ascii case is ignored
+    fn colon(&mut self, ident: String, value: Value) -> Result<bool, VisitorError> {
+        let (column, field) = self.field(&ident)?;
+        let (Some(field), Some(pattern)) =
+            (Self::text(field), Self::text(&self.resolve(&column, value)))
+        else {
+            return Ok(false);
+        };
+        let pattern = regex::escape(&pattern).replace('%', ".*").replace('_', ".");
+        let pattern = regex::RegexBuilder::new(&format!("^{pattern}$"))
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| VisitorError::InvalidRegex(e.to_string()))?;
+        Ok(pattern.is_match(&field))
+    }
+    fn regex(&mut self, ident: String, value: Value) -> Result<bool, VisitorError> {
+        let (_, field) = self.field(&ident)?;
+        let pattern = match value {
+            Value::String(pattern) => regex::Regex::new(&pattern)
+                .map_err(|e| VisitorError::InvalidRegex(e.to_string()))?,
+            Value::Null => return Ok(false),
+            other => {
+                return Err(VisitorError::InvalidTypeUsedWithRegexOperator(
+                    json_type_name(&other),
+                ))
+            }
+        };
+        Ok(Self::text(field).is_some_and(|field| pattern.is_match(&field)))
+    }
+
+    fn or(&mut self, ls: bool, rs: bool) -> Result<bool, VisitorError> {
+        Ok(ls || rs)
+    }
+    fn and(&mut self, ls: bool, rs: bool) -> Result<bool, VisitorError> {
+        Ok(ls && rs)
+    }
+    fn not(&mut self, expr: bool) -> Result<bool, VisitorError> {
+        Ok(!expr)
+    }
 }
 
 impl ToSql<Text, Sqlite> for QCChecklist {
@@ -164,8 +553,7 @@ impl ToSql<Text, Sqlite> for QCChecklist {
         for (key, val) in &self.0 {
             string.push_str(key);
             string.push(':');
-            string.push(val.0[0].as_char());
-            string.push(val.0[1].as_char());
+            string.push_str(&val.encode());
             string.push(',');
         }
         out.set_value(string);
@@ -183,15 +571,7 @@ impl FromSql<Text, Sqlite> for QCChecklist {
             .filter(|v| !v.is_empty())
             .map(|v| v.split_once(':').ok_or_else(|| "Invalid entry".into()))
             .map(|r| match r {
-                Ok((key, val)) => Ok((key.to_owned(), {
-                    let b = val.as_bytes();
-                    if b.len() != 2 {
-                        return Err("Invalid answer length".into());
-                    }
-                    let qca1 = b[0] as char;
-                    let qca2 = b[1] as char;
-                    QuestionAnswers([qca1.try_into()?, qca2.try_into()?])
-                })),
+                Ok((key, val)) => Ok((key.to_owned(), QuestionAnswers::parse(val)?)),
                 Err(e) => Err(e),
             })
             .collect::<diesel::deserialize::Result<_>>()?;
@@ -199,3 +579,353 @@ impl FromSql<Text, Sqlite> for QCChecklist {
         Ok(Self(inner))
     }
 }
+
+/// Who gave one pass of a question its answer, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AnswerDetail {
+    // always the same as the compact answer in `qc_answers`
+    pub answer: QuestionAnswer,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answered_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answered_at: Option<Time>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    // where the evidence for the answer lives, ex the file name of a photo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<String>,
+    // what was measured or picked for typed questions, see `config::Question::grade`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+}
+
+impl Default for AnswerDetail {
+    fn default() -> Self {
+        Self {
+            answer: QuestionAnswer::Incomplete,
+            answered_by: None,
+            answered_at: None,
+            note: None,
+            attachment: None,
+            value: None,
+        }
+    }
+}
+
+/// The answers of `qc_answers` with who answered them and when, keyed by question
+#[derive(Debug, Default, Clone, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(crate = "rocket::serde")]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub struct QCAnswerDetails(pub HashMap<String, Vec<AnswerDetail>>);
+
+impl QCAnswerDetails {
+    /// Lines the details up with `answers`, which stay the source of truth. Passes whose
+    /// answer didn't change since `previous` keep who answered them and when, new answers
+    /// are stamped with `now` and the initials of their pass unless they say otherwise
+    pub fn sync(
+        &mut self,
+        previous: Option<&QCAnswerDetails>,
+        answers: &QCChecklist,
+        initials: [Option<&str>; 2],
+        now: Time,
+    ) {
+        let mut details = std::mem::take(&mut self.0);
+        for (question, answers) in &answers.0 {
+            let sent = details.remove(question);
+            // passes the request left out start off as the previous ones
+            let carried_over = sent.is_none();
+            let mut passes = sent
+                .or_else(|| previous.and_then(|p| p.0.get(question).cloned()))
+                .unwrap_or_default();
+            passes.resize_with(answers.0.len(), Default::default);
+
+            for (pass, (detail, &answer)) in passes.iter_mut().zip(&answers.0).enumerate() {
+                let before = previous
+                    .and_then(|p| p.0.get(question))
+                    .and_then(|passes| passes.get(pass))
+                    .filter(|before| before.answer == answer);
+                detail.answer = answer;
+                if answer == QuestionAnswer::Incomplete {
+                    detail.answered_by = None;
+                    detail.answered_at = None;
+                    detail.value = None;
+                    continue;
+                }
+                if answer == QuestionAnswer::NA {
+                    detail.value = None;
+                }
+                if let Some(before) = before {
+                    if detail.answered_by.is_none() {
+                        detail.answered_by.clone_from(&before.answered_by);
+                    }
+                    // stays unknown for answers given before details were kept
+                    detail.answered_at = detail.answered_at.or(before.answered_at);
+                } else if carried_over {
+                    // a changed answer is a new one, what was said about the old one is stale
+                    detail.answered_by = None;
+                    detail.note = None;
+                    detail.attachment = None;
+                    detail.answered_at = Some(now);
+                } else {
+                    detail.answered_at.get_or_insert(now);
+                }
+                if detail.answered_by.is_none() {
+                    // passes past the second one have no initials on the form
+                    detail.answered_by = initials.get(pass).copied().flatten().map(str::to_owned);
+                }
+            }
+            self.0.insert(question.clone(), passes);
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for QCAnswerDetails {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Sqlite>,
+    ) -> diesel::serialize::Result {
+        out.set_value(serde_json::to_string(&self.0)?);
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for QCAnswerDetails {
+    fn from_sql(bytes: <Sqlite as Backend>::RawValue<'_>) -> diesel::deserialize::Result<Self> {
+        let val = <String as diesel::deserialize::FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(Self(serde_json::from_str(&val)?))
+    }
+}
+
+#[test]
+fn test_answer_details() {
+    let answers = |a: &str| {
+        let mut checklist = QCChecklist::new();
+        checklist.0.insert(
+            "harddrive_health".to_owned(),
+            QuestionAnswers::parse(a).unwrap(),
+        );
+        checklist
+    };
+    let then = Time(time::OffsetDateTime::UNIX_EPOCH);
+    let now = Time(time::OffsetDateTime::now_utc());
+
+    let mut details = QCAnswerDetails::default();
+    details.sync(None, &answers("pi"), [Some("AB"), None], then);
+    let passes = &details.0["harddrive_health"];
+    assert_eq!(passes[0].answered_by.as_deref(), Some("AB"));
+    assert_eq!(passes[0].answered_at, Some(then));
+    assert_eq!(passes[1], AnswerDetail::default());
+
+    // the first pass is unchanged, the second one failed with a note
+    let previous = details.clone();
+    let mut update: QCAnswerDetails = serde_json::from_str(
+        r#"{"harddrive_health": [{"answer": "p"}, {"answer": "f", "note": "reallocated sectors"}]}"#,
+    )
+    .unwrap();
+    update.sync(
+        Some(&previous),
+        &answers("pf"),
+        [Some("AB"), Some("CD")],
+        now,
+    );
+    let passes = &update.0["harddrive_health"];
+    assert_eq!(passes[0].answered_at, Some(then));
+    assert_eq!(passes[1].answered_by.as_deref(), Some("CD"));
+    assert_eq!(passes[1].answered_at, Some(now));
+    assert_eq!(passes[1].note.as_deref(), Some("reallocated sectors"));
+
+    // answers without details keep the previous ones while they are unchanged
+    let later = Time(now.0 + time::Duration::minutes(5));
+    let mut details = QCAnswerDetails::default();
+    details.sync(
+        Some(&update),
+        &answers("pf"),
+        [Some("EF"), Some("GH")],
+        later,
+    );
+    let passes = &details.0["harddrive_health"];
+    assert_eq!(passes[1].answered_by.as_deref(), Some("CD"));
+    assert_eq!(passes[1].answered_at, Some(now));
+    assert_eq!(passes[1].note.as_deref(), Some("reallocated sectors"));
+
+    // a changed answer without details belongs to whoever changed it, not the old note
+    let mut details = QCAnswerDetails::default();
+    details.sync(
+        Some(&update),
+        &answers("pp"),
+        [Some("EF"), Some("GH")],
+        later,
+    );
+    let passes = &details.0["harddrive_health"];
+    assert_eq!(passes[0].answered_by.as_deref(), Some("AB"));
+    assert_eq!(passes[0].answered_at, Some(then));
+    assert_eq!(passes[1].answer, QuestionAnswer::Pass);
+    assert_eq!(passes[1].answered_by.as_deref(), Some("GH"));
+    assert_eq!(passes[1].answered_at, Some(later));
+    assert_eq!(passes[1].note, None);
+}
+
+#[test]
+fn test_pass_counts() {
+    let mut config = Config::load_from_file("./config.json5").expect("Failed to load config file");
+    config.0["build_types"]["values"]["desktop"]["passes"] = 1.into();
+    config.0["qc_checks"]["questions"]["harddrive_health"]["passes"] = 3.into();
+
+    assert_eq!(pass_count(&config, "laptop", "bios_date"), DEFAULT_PASSES);
+    assert_eq!(pass_count(&config, "desktop", "bios_date"), 1);
+    assert_eq!(pass_count(&config, "desktop", "harddrive_health"), 3);
+
+    let checklist: QCChecklist =
+        serde_json::from_str(r#"{"bios_date": "p", "harddrive_health": "pfn"}"#).unwrap();
+    assert_eq!(checklist.0["harddrive_health"].encode(), "pfn");
+    assert!(checklist.check_passes(&config, "desktop").is_ok());
+    assert!(matches!(
+        checklist.check_passes(&config, "laptop"),
+        Err(DataBaseError::InvalidPassCount {
+            expected: 2,
+            found: 1,
+            ..
+        })
+    ));
+
+    assert!(QuestionAnswers::parse("").is_err());
+    assert_eq!(QuestionAnswers::parse("PF").unwrap().encode(), "pf");
+}
+
+#[test]
+fn test_typed_questions() {
+    let config = Config::load_from_file("./config.json5").expect("Failed to load config file");
+
+    assert_eq!(
+        config.question("bios_date").map(|q| q.kind),
+        Some(QuestionKind::Check)
+    );
+    let battery = config.question("battery_health").unwrap();
+    assert_eq!(battery.grade(&85.into()), Ok(QuestionAnswer::Pass));
+    assert_eq!(battery.grade(&61.5.into()), Ok(QuestionAnswer::Fail));
+    assert!(battery.grade(&"85".into()).is_err());
+
+    let grade = config.question("cosmetic_grade").unwrap();
+    assert_eq!(grade.grade(&"B".into()), Ok(QuestionAnswer::Pass));
+    assert_eq!(grade.grade(&"D".into()), Ok(QuestionAnswer::Fail));
+    assert!(grade.grade(&"E".into()).is_err());
+
+    let version = config.question("bios_version").unwrap();
+    assert_eq!(version.grade(&"A12".into()), Ok(QuestionAnswer::Pass));
+    assert!(version.grade(&" ".into()).is_err());
+
+    // answered passes take their answer from the value, falling back on the previous one
+    let mut checklist: QCChecklist =
+        serde_json::from_str(r#"{"battery_health": "pp", "bios_date": "pi"}"#).unwrap();
+    let previous: QCAnswerDetails =
+        serde_json::from_str(r#"{"battery_health": [{"answer": "p", "value": 90}]}"#).unwrap();
+    let details: QCAnswerDetails = serde_json::from_str(
+        r#"{"battery_health": [{"answer": "p", "value": 90}, {"answer": "p", "value": 70}]}"#,
+    )
+    .unwrap();
+    checklist.grade(&config, &details, Some(&previous)).unwrap();
+    assert_eq!(checklist.0["battery_health"].encode(), "pf");
+    assert_eq!(checklist.0["bios_date"].encode(), "pi");
+
+    let mut checklist: QCChecklist = serde_json::from_str(r#"{"battery_health": "pp"}"#).unwrap();
+    assert!(matches!(
+        checklist.grade(&config, &QCAnswerDetails::default(), Some(&previous)),
+        Err(DataBaseError::MissingAnswerValue(_))
+    ));
+}
+
+#[test]
+fn test_applicability() {
+    let mut config = Config::load_from_file("./config.json5").expect("Failed to load config file");
+    let rules = [
+        (
+            "bios_date",
+            r#"drive_type = "Hard Disk Drive" & !operating_system = "linux""#,
+        ),
+        ("cmos_battery", "mso_installed = true; ram_size >= 16GiB"),
+        ("post_errors", "asm_serial = null | asm_serial: CFS*"),
+    ];
+    for (question, rule) in rules {
+        config.0["qc_checks"]["questions"][question]["applies_when"] = rule.into();
+    }
+
+    let form = serde_json::json!({
+        "build_type": "desktop",
+        "drive_type": "hdd",
+        "operating_system": "win11",
+        "mso_installed": true,
+        "ram_size": "GiB032",
+    });
+    let applicable = applicable_questions(&config, &form).unwrap();
+    for question in [
+        "bios_date",
+        "cmos_battery",
+        "post_errors",
+        "external_case_damage",
+    ] {
+        assert!(applicable.iter().any(|q| q == question), "{question}");
+    }
+    assert!(!applicable.iter().any(|q| q == "check_battery"));
+
+    let form = serde_json::json!({
+        "build_type": "laptop",
+        "drive_type": "ssd",
+        "mso_installed": true,
+        "ram_size": "GiB008",
+        "asm_serial": "OTR-001",
+    });
+    let applicable = applicable_questions(&config, &form).unwrap();
+    for question in ["bios_date", "cmos_battery", "post_errors"] {
+        assert!(!applicable.iter().any(|q| q == question), "{question}");
+    }
+    assert!(applicable.iter().any(|q| q == "check_battery"));
+
+    let mut items = config.0;
+    items["qc_checks"]["questions"]["bios_date"]["applies_when"] = "not_a_field = 1".into();
+    assert!(applies(&Config::new(items), "bios_date", &form).is_err());
+}
+
+#[test]
+fn test_summary() {
+    let config = Config::load_from_file("./config.json5").expect("Failed to load config file");
+    let form = serde_json::json!({"build_type": "desktop", "drive_type": "ssd"});
+    let applicable = applicable_questions(&config, &form).unwrap();
+
+    let mut checklist = QCChecklist::new();
+    for question in &applicable {
+        checklist
+            .0
+            .insert(question.clone(), QuestionAnswers::parse("pp").unwrap());
+    }
+    // answers to questions that don't apply aren't counted
+    checklist.0.insert(
+        "check_battery".to_owned(),
+        QuestionAnswers::parse("ff").unwrap(),
+    );
+    let summary = checklist.summary(&config, &form).unwrap();
+    assert_eq!(summary.applicable, applicable.len());
+    assert_eq!(summary.passes.len(), 2);
+    assert_eq!(summary.passes[1].pass, applicable.len());
+    assert!(summary.failing.is_empty());
+    assert!(summary.ready_to_finalize);
+
+    checklist.0.insert(
+        "bios_date".to_owned(),
+        QuestionAnswers::parse("pf").unwrap(),
+    );
+    checklist.0.remove("post_errors");
+    let summary = checklist.summary(&config, &form).unwrap();
+    assert_eq!(summary.failing, ["bios_date"]);
+    assert_eq!(summary.unanswered, ["post_errors"]);
+    assert_eq!(
+        summary.passes[1],
+        PassSummary {
+            pass: applicable.len() - 2,
+            fail: 1,
+            na: 0,
+            incomplete: 1
+        }
+    );
+    assert!(!summary.ready_to_finalize);
+}
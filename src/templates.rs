@@ -6,7 +6,7 @@ use serde_json::Value;
 
 use crate::{
     database::{self, Db},
-    Config,
+    Config, SharedConfig,
 };
 
 //idk if this is needed but whatever
@@ -35,6 +35,12 @@ async fn qc_form_provided(
         })
         .collect();
 
+    // a new form is shown for the location picked so far, see `Config::for_location`
+    let items = match values.get("build_location").and_then(Value::as_str) {
+        Some(location) => items.for_location(location),
+        None => items,
+    };
+
     Ok(Template::render(
         "qc_form",
         context! {
@@ -45,8 +51,17 @@ async fn qc_form_provided(
 }
 
 #[get("/qc_form/<id>", rank = 1)]
-async fn qc_form_id(items: &Config, id: i32, db: Db) -> database::Result<Template> {
+async fn qc_form_id(items: SharedConfig, id: i32, db: Db) -> database::Result<Template> {
     let values = db.get_form(id).await?;
+    // existing forms keep the questions they were created with
+    let items = db
+        .pinned_config(
+            items,
+            values.build_location.clone(),
+            values.checklist_version,
+        )
+        .await?;
+    let values = database::SummarizedQCForm::new(&items, values)?;
     Ok(Template::render(
         "qc_form",
         context! {
@@ -56,32 +71,28 @@ async fn qc_form_id(items: &Config, id: i32, db: Db) -> database::Result<Templat
     ))
 }
 
-#[get("/database")]
-async fn database_page(items: &Config) -> Template {
+#[get("/database?<view>")]
+async fn database_page(items: &Config, view: Option<&str>) -> Template {
     Template::render(
         "database",
         context! {
             items: &items.0,
+            view,
         },
     )
 }
 
 #[get("/printable/<id>?finalize")]
-pub async fn printable_finaize(items: &Config, id: i32, db: Db) -> database::Result<Template> {
-    use database::schema::qc_forms;
-    use diesel::*;
-    let values = db
-        .run(move |conn| {
-            diesel::update(qc_forms::table.find(id))
-                .set(qc_forms::finalized.eq(true))
-                .execute(conn)?;
-            database::Result::<database::ExistingQCForm>::Ok(
-                database::schema::qc_forms::table
-                    .find(id)
-                    .get_result::<database::ExistingQCForm>(conn)?,
-            )
-        })
+pub async fn printable_finaize(items: SharedConfig, id: i32, db: Db) -> database::Result<Template> {
+    let values = db.finalize_form(id).await?;
+    let items = db
+        .pinned_config(
+            items,
+            values.build_location.clone(),
+            values.checklist_version,
+        )
         .await?;
+    let values = database::SummarizedQCForm::new(&items, values)?;
 
     Ok(Template::render(
         "printable",
@@ -93,8 +104,16 @@ pub async fn printable_finaize(items: &Config, id: i32, db: Db) -> database::Res
 }
 
 #[get("/printable/<id>")]
-pub async fn printable(items: &Config, id: i32, db: Db) -> database::Result<Template> {
+pub async fn printable(items: SharedConfig, id: i32, db: Db) -> database::Result<Template> {
     let values = db.get_form(id).await?;
+    let items = db
+        .pinned_config(
+            items,
+            values.build_location.clone(),
+            values.checklist_version,
+        )
+        .await?;
+    let values = database::SummarizedQCForm::new(&items, values)?;
 
     Ok(Template::render(
         "printable",
@@ -9,7 +9,7 @@ use diesel::{
 };
 use time::OffsetDateTime;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
 #[serde(crate = "rocket::serde")]
 #[diesel(sql_type = diesel::sql_types::TimestamptzSqlite)]
 pub struct Time(#[serde(with = "time::serde::iso8601")] pub time::OffsetDateTime);
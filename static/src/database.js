@@ -141,7 +141,7 @@ async function make_search() {
         var offset = parseInt(document.getElementById("table_entry_page").value) * limit;
         console.log(offset);
     
-        let res = await search(limit, search_par, order_table_glob, ascending_glob, offset);
+        let res = await search(limit, search_par, order_table_glob, ascending_glob, offset, requestedColumns());
         console.log(res.status);
         if (res.status != 200){
             console.error(res);
@@ -160,6 +160,19 @@ function converte_base2_size_to_base10(size){
 }
 
 
+// only the visible columns are fetched, the totals are computed from qc_answers
+function requestedColumns() {
+    let columns = [];
+    for (let column of visibleColumns()) {
+        if (db_columns.includes(column)) {
+            columns.push(column);
+        } else if (column.startsWith("total_") && !columns.includes("qc_answers")) {
+            columns.push("qc_answers");
+        }
+    }
+    return columns;
+}
+
 function updateVisibleComumns(current) {
     var table = document.getElementById("qurry_list");
     var checkboxes = document.getElementsByClassName("row_visibility_button");
@@ -175,5 +188,79 @@ function updateVisibleComumns(current) {
             }
         }
     }
+    if (current !== undefined) {
+        // newly shown columns haven't been fetched yet
+        sleep(17).then(make_search);
+    }
+}
+function visibleColumns() {
+    let checkboxes = document.getElementsByClassName("row_visibility_button");
+    let columns = [];
+    for (var c = 0; c < checkboxes.length; c++) {
+        if (checkboxes[c].classList.contains("active")) {
+            // key_map has the id column at the front
+            columns.push(key_map[c + 1][0]);
+        }
+    }
+    return columns;
+}
+
+async function save_view() {
+    let name = prompt("Name of the saved search");
+    if (name == null || name.trim().length == 0) {
+        return;
+    }
+    let owner = prompt("Your initials (leave blank to share with everyone)", localStorage.getItem("search_owner") ?? "");
+    if (owner == null) {
+        return;
+    }
+    owner = owner.trim();
+    if (owner.length != 0) {
+        localStorage.setItem("search_owner", owner);
+    }
+
+    let res = await new_saved_search(JSON.stringify({
+        "name": name.trim(),
+        "owner": owner,
+        "shared": owner.length == 0,
+        "search": document.getElementById("databse_search_parameters").value,
+        "order_table": order_table_glob,
+        "ascending": ascending_glob != "false",
+        "visible_columns": visibleColumns(),
+    }));
+    if (res.status != 201) {
+        alert(JSON.stringify(await res.json()));
+    } else {
+        let url = new URL(window.location);
+        url.searchParams.set("view", name.trim());
+        window.history.replaceState(null, "", url);
+    }
+}
+
+async function load_view(view) {
+    if (view != null) {
+        let res = await get_saved_search(view, localStorage.getItem("search_owner"));
+        if (res.status != 200) {
+            alert("No saved search named '" + view + "'");
+        } else {
+            let saved = await res.json();
+            document.getElementById("databse_search_parameters").value = saved.search;
+            order_table_glob = saved.order_table;
+            ascending_glob = saved.ascending ? "true" : "false";
+
+            if (saved.visible_columns != null) {
+                let checkboxes = document.getElementsByClassName("row_visibility_button");
+                for (var c = 0; c < checkboxes.length; c++) {
+                    if (saved.visible_columns.includes(key_map[c + 1][0])) {
+                        checkboxes[c].classList.add("active");
+                    } else {
+                        checkboxes[c].classList.remove("active");
+                    }
+                }
+            }
+        }
+    }
+    await make_search();
 }
-make_search()
\ No newline at end of file
+
+load_view(initial_view)
\ No newline at end of file
@@ -10,6 +10,17 @@ async function new_post(request_body) {
 }
 
 
+async function applicable_questions(request_body) {
+    return fetch("/api/applicable_questions", {
+        method: "POST",
+        body: request_body,
+        headers: {
+            "Content-type": "application/json; charset=UTF-8"
+        }
+    })
+}
+
+
 async function update_post(id, request_body) {
     return fetch("/api/update_post/" + id, {
         method: "POST",
@@ -58,7 +69,7 @@ async function delete_post(id, password) {
     })
 }
 
-async function search(limit, query, sortby, ascending, offset) {
+async function search(limit, query, sortby, ascending, offset, columns) {
     return fetch("/api/search", {
         method: "POST",
         mode: "cors",
@@ -68,10 +79,40 @@ async function search(limit, query, sortby, ascending, offset) {
             "order_table": (sortby != null) ? sortby : "",
             "ascending": ascending,
             "offset": (offset != null) ? offset : "",
+            "columns": (columns != null) ? columns.join(",") : "",
         }),
     })
 }
 
+async function get_saved_search(name, owner) {
+    let params = (owner != null && owner.length != 0) ? "?" + new URLSearchParams({"owner": owner}) : "";
+    return fetch("/api/get_saved_search/" + encodeURIComponent(name) + params, {
+        method: "GET",
+        headers: {
+            "Content-type": "application/json; charset=UTF-8"
+        }
+    })
+}
+
+async function new_saved_search(request_body) {
+    return fetch("/api/new_saved_search", {
+        method: "POST",
+        body: request_body,
+        headers: {
+            "Content-type": "application/json; charset=UTF-8"
+        }
+    })
+}
+
+async function device_models(search) {
+    return fetch("/api/device_models?" + new URLSearchParams({"search": search}), {
+        method: "GET",
+        headers: {
+            "Content-type": "application/json; charset=UTF-8"
+        }
+    })
+}
+
 function to_db_date(date) {
     let year = date.getUTCFullYear();
     year = (year<0 ? "-" : "") + Math.abs(year).toString().padStart(4, "0")
@@ -14,6 +14,8 @@ let download_id_on_save = urlParams.has('download_id_on_save');
 
 
 const qcform = document.querySelector("#qc-form");
+// the saved qc_answer_details, typed questions send their values back through it
+let answer_details = {};
 
 
 async function save_form() {
@@ -100,6 +102,12 @@ function update_form_values(json) {
             case "qc_answers":
                 qc_answers = value;
                 break;
+            case "qc_answer_details":
+                answer_details = value;
+                break;
+            case "summary":
+                // worked out by the server, nothing to fill in
+                break;
             case "metadata":
                 metadata = value;
                 break
@@ -131,6 +139,8 @@ function update_form_values(json) {
                     document.getElementById(key).value = value;
                     if (key === "build_type" && json.id == null){
                         update_build_type();
+                    } else if (key === "build_type"){
+                        update_passes(value);
                     }
                 } catch (e) {
                     console.error(e, key, value)
@@ -195,22 +205,17 @@ function update_qc_questions(qc, only_show_given){
         let question_id = question.getAttribute("question_id");
         
         if(qc[question_id] != null){
-            let qc1_answer = qc[question_id][0];
-            let q1 = question.getElementsByClassName('qc1');
-            q1[0].checked = false;
-            q1[1].checked = false;
-            q1[2].checked = false;
-            q1[3].checked = false;
-            question.querySelector(".qc1[value='"+qc1_answer+"']").checked = true;
-
-            
-            let qc2_answer = qc[question_id][1];
-            let q2 = question.getElementsByClassName('qc2');
-            q2[0].checked = false;
-            q2[1].checked = false;
-            q2[2].checked = false;
-            q2[3].checked = false;
-            question.querySelector(".qc2[value='"+qc2_answer+"']").checked = true;
+            let passes = question.querySelectorAll(".radio-toolbar[pass]");
+            for(let pass = 0; pass < passes.length; pass ++){
+                // passes the form was saved without stay incomplete
+                let answer = qc[question_id].charAt(pass).toLowerCase() || "i";
+                if (passes[pass].classList.contains("typed-answer")){
+                    passes[pass].querySelector(".answer-na").checked = answer == "n";
+                    passes[pass].querySelector(".answer-value").value = answer_details[question_id]?.[pass]?.value ?? "";
+                }else{
+                    passes[pass].querySelector("input[value='"+answer+"']").checked = true;
+                }
+            }
         }
 
         if (only_show_given){
@@ -249,6 +254,7 @@ function form_to_json(){
 
     let form = {};
     form.qc_answers = collect_qc_questions();
+    form.qc_answer_details = collect_qc_values();
 
     let form_items = document.getElementsByClassName("qc-form-item");
 
@@ -288,21 +294,63 @@ function collect_qc_questions(){
         if (key == null || key.trim().length == 0){
             continue;
         }
-        let qc1_val = question.querySelector('.qc1:checked').value;
-        if (qc1_val == null){
-            qc1_val = "i";
-        }
-        let qc2_val = question.querySelector('.qc2:checked').value;
-        if (qc2_val == null){
-            qc2_val = "i";
+        let answer = "";
+        let passes = question.querySelectorAll(".radio-toolbar[pass]:not([hidden])");
+        for(let pass = 0; pass < passes.length; pass ++){
+            answer += typed_answer(passes[pass]) ?? passes[pass].querySelector("input:checked")?.value ?? "i";
         }
 
-        answers[key] = qc1_val + qc2_val;
+        answers[key] = answer;
     }
     return answers;
 }
 
-function update_build_type() {
+// the value of a pass of a typed question, null for the pass/fail ones
+function typed_value(pass){
+    let input = pass.querySelector(".answer-value");
+    if (input == null || input.value.trim().length == 0){
+        return null;
+    }
+    return input.type == "number" ? Number(input.value) : input.value.trim();
+}
+
+// typed questions are marked answered while the server decides if they passed
+function typed_answer(pass){
+    if (!pass.classList.contains("typed-answer")){
+        return null;
+    }
+    if (pass.querySelector(".answer-na").checked){
+        return "n";
+    }
+    return typed_value(pass) == null ? "i" : "p";
+}
+
+function collect_qc_values(){
+    let questions = document.querySelectorAll(".qc-check-answer:not([hidden])");
+    let details = {};
+
+    for(let i = 0; i < questions.length; i ++){
+        let key = questions[i].getAttribute("question_id");
+        let passes = questions[i].querySelectorAll(".typed-answer[pass]:not([hidden])");
+        if (passes.length == 0){
+            continue;
+        }
+        details[key] = [];
+        for(let pass = 0; pass < passes.length; pass ++){
+            let previous = answer_details[key]?.[pass] ?? {};
+            details[key].push({
+                answer: typed_answer(passes[pass]),
+                value: typed_value(passes[pass]),
+                note: previous.note,
+                attachment: previous.attachment,
+            });
+        }
+    }
+    return details;
+}
+
+// the server decides which questions apply to the form as it is filled in so far
+async function update_build_type() {
     
     let build_type = document.getElementById("build_type").value;
 
@@ -310,20 +358,117 @@ function update_build_type() {
         return;
     }
 
-    let whitelists = document.querySelectorAll("[whitelist_build_types]");
-    for(let i = 0; i < whitelists.length; i ++){
-        let whitelist = whitelists[i];
-        let allowed = whitelist.getAttribute("whitelist_build_types").split(" ").includes(build_type.trim());
-        
-        if (allowed){
-            whitelist.removeAttribute("hidden");
-        }else{
-            whitelist.setAttribute("hidden", "");
-        }
+    let res = await applicable_questions(JSON.stringify(form_to_json()));
+    if (!res.ok){
+        console.error("Failed to get the applicable questions", await res.text());
+        return;
     }
+    let applicable = await res.json();
+
+    let questions = document.querySelectorAll("[question_id]");
+    for(let i = 0; i < questions.length; i ++){
+        let allowed = applicable.includes(questions[i].getAttribute("question_id"));
+        questions[i].toggleAttribute("hidden", !allowed);
+    }
+    update_passes(build_type.trim());
     hide_empty_section();
 }
 
+// rules can depend on any field, existing forms only show what they were saved with
+qcform.addEventListener("change", (event) => {
+    if (edit_id == null && event.target.id == "build_location"){
+        show_for_location();
+    } else if (event.target.id == "make_model" && fill_device_model_defaults() && edit_id == null){
+        update_build_type();
+    } else if (edit_id == null && event.target.classList.contains("qc-form-item") && event.target.id != "build_type"){
+        update_build_type();
+    }
+});
+
+// the catalog entries matching what was typed so far, by the name forms save them under
+let device_model_matches = {};
+
+document.getElementById("make_model").addEventListener("input", async (event) => {
+    let res = await device_models(event.target.value);
+    if (!res.ok){
+        console.error("Failed to get the device models", await res.text());
+        return;
+    }
+    device_model_matches = {};
+    let list = document.getElementById("device_models");
+    list.replaceChildren();
+    for (const device_model of await res.json()){
+        device_model_matches[device_model.name] = device_model;
+        let option = document.createElement("option");
+        option.value = device_model.name;
+        if (device_model.form_factor){
+            option.label = device_model.form_factor;
+        }
+        list.appendChild(option);
+    }
+});
+
+// picking a model from the catalog fills in its defaults where nothing was chosen yet
+function fill_device_model_defaults() {
+    let device_model = device_model_matches[document.getElementById("make_model").value];
+    if (device_model == null){
+        return false;
+    }
+    let filled = false;
+    for (const key of ["build_type", "drive_type", "drive_size", "ram_type", "ram_size"]){
+        let select = document.getElementById(key);
+        if (device_model[key] != null && !select.value){
+            select.value = device_model[key];
+            filled = true;
+        }
+    }
+    return filled;
+}
+
+// build locations can have their own questions and patterns, so a new form is
+// rendered again for the picked one with what was filled in so far
+function show_for_location() {
+    let params = new URLSearchParams();
+    if (download_id_on_save){
+        params.set("download_id_on_save", "");
+    }
+    for (const [key, value] of Object.entries(form_to_json())){
+        if (value != null){
+            params.set(key, JSON.stringify(value));
+        }
+    }
+    window.location.replace("/qc_form?" + params);
+}
+
+// mirrors `qc_checklist::pass_count` on the server
+function pass_count(question_id, build_type) {
+    return qc_checks.questions[question_id]?.passes
+        ?? build_types.values[build_type]?.passes
+        ?? qc_checks.passes
+        ?? 2;
+}
+
+// only shows as many answer columns as the build type needs for each question
+function update_passes(build_type) {
+    let most = 0;
+    let questions = document.getElementsByClassName("qc-check-answer");
+    for(let i = 0; i < questions.length; i ++){
+        let count = pass_count(questions[i].getAttribute("question_id"), build_type);
+        if (!questions[i].hasAttribute("hidden")){
+            most = Math.max(most, count);
+        }
+        let passes = questions[i].querySelectorAll(".radio-toolbar[pass]");
+        for(let pass = 0; pass < passes.length; pass ++){
+            passes[pass].toggleAttribute("hidden", pass >= count);
+        }
+    }
+
+    let headers = document.querySelectorAll("[qc_check_section_heading] > [pass]");
+    for(let i = 0; i < headers.length; i ++){
+        headers[i].toggleAttribute("hidden", Number(headers[i].getAttribute("pass")) >= most);
+    }
+}
+
 function hide_empty_section(){
     let section = 0;
     while(true){
@@ -344,4 +489,5 @@ function hide_empty_section(){
         }
         section += 1;
     }
-}
\ No newline at end of file
+}
+update_passes(document.getElementById("build_type").value.trim());
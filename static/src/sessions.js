@@ -1,3 +1,55 @@
+// tokens of the sessions this browser created or was given
+function session_tokens() {
+    return JSON.parse(localStorage.getItem("session_tokens") ?? "{}");
+}
+
+function remember_session_token(session_id, token) {
+    let tokens = session_tokens();
+    if (token == null) {
+        delete tokens[session_id];
+    } else {
+        tokens[session_id] = token;
+    }
+    localStorage.setItem("session_tokens", JSON.stringify(tokens));
+}
+
+function session_token(session_id) {
+    let token = session_tokens()[session_id];
+    if (token == null) {
+        token = prompt("Token for session " + session_id);
+        if (token != null) {
+            token = token.trim();
+            remember_session_token(session_id, token);
+        }
+    }
+    return token;
+}
+
+async function create_session(session_id, build_location) {
+    let res = await fetch("/api/new_session/" + session_id, {
+        method: "POST",
+        mode: "cors",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ "build_location": build_location }),
+    });
+    if (res.status != 201) {
+        return null;
+    }
+    let created = await res.json();
+    remember_session_token(session_id, created.token);
+    return created;
+}
+
+// the query every connection to a session has to send
+function session_query(session_id) {
+    let query = new URLSearchParams({ "token": session_token(session_id) ?? "" });
+    let build_location = document.getElementById("build_location")?.value ?? localStorage.getItem("build_location");
+    if (build_location != null && build_location != "") {
+        query.set("build_location", build_location);
+    }
+    return query;
+}
+
 
 
 class AutoFillSession {
@@ -67,7 +119,11 @@ class AutoFillSession {
             return;
         }
         await this.close_websocket();
-        this.ws = new WebSocket("ws://" + location.host + "/api/consume_single/" + session_id);
+        this.session_id = session_id;
+        let query = session_query(session_id);
+        // items are acked once filled in, so they aren't lost if the page goes away first
+        query.set("version", 2);
+        this.ws = new WebSocket("ws://" + location.host + "/api/consume_single/" + session_id + "?" + query);
         
         this.ws.addEventListener("close", this.close);
         this.ws.addEventListener("open", this.open);
@@ -111,12 +167,38 @@ class AutoFillSession {
         this.input.removeAttribute("data-readonly");
     }
 
+    // typed payloads name the form fields they belong to
+    fill_fields(fields) {
+        for (let field in fields) {
+            let input = document.getElementById(field);
+            if (input == null || input == this.input) {
+                continue;
+            }
+            if (input.type == "checkbox") {
+                input.checked = fields[field];
+            } else {
+                input.value = fields[field] == null ? "" : fields[field];
+            }
+            input.dispatchEvent(new Event("input"));
+        }
+        if (this.input.id in fields) {
+            this.input.value = fields[this.input.id];
+        } else {
+            this.input.value = "Filled " + Object.keys(fields).join(", ");
+        }
+    }
+
     event_open(event) {
         this.input.value = "Connected";
     }
 
     event_close(event) {
-        if (event.code != 1006) {
+        if (event.reason == "Invalid session token") {
+            remember_session_token(this.session_id, null);
+        }
+        if (event.reason == "Acked") {
+            // keeps what was filled in
+        } else if (event.code != 1006) {
             console.log(event.reason);
             if (event.reason.trim().length > 0) {
                 this.input.value = event.reason;
@@ -137,13 +219,21 @@ class AutoFillSession {
 
     async event_message(event) {
         let json = JSON.parse(event.data);
-        if (json.SessionQueuePos != null) {
-            this.input.value = ("Connected in Queue: " + json.SessionQueuePos);
-        } else if (json.NoSessionQueuePos != null) {
-            this.input.value = ("Connecting in Queue: " + json.NoSessionQueuePos);
-        } else if (json.Consumed != undefined || json.Consumed != null) {
-            this.input.value = json.Consumed;
-        }else{
+        if (json.Hello != null || json == "Pong") {
+            return;
+        } else if (json.QueuePos != null) {
+            this.input.value = (json.QueuePos.live ? "Connected in Queue: " : "Connecting in Queue: ") + json.QueuePos.position;
+        } else if (json.Deliver != null) {
+            let item = json.Deliver.item;
+            if (typeof item == "object" && item != null) {
+                this.fill_fields(item);
+            } else {
+                this.input.value = item;
+            }
+            this.ws.send(JSON.stringify({ "Ack": json.Deliver.id }));
+        } else if (json.Error != null) {
+            console.error(json.Error);
+        } else {
             await this.close_websocket();
             this.input.value = "Malformed Message";
             this.input.setAttribute("data-readonly", false);
@@ -165,12 +255,29 @@ function close_promise(ws) {
 }
 
 
+// identifies this device so it can re-attach to its queue after dropping out
+function producer_id() {
+    let id = localStorage.getItem("session_producer");
+    if (id == null) {
+        id = crypto.randomUUID();
+        localStorage.setItem("session_producer", id);
+    }
+    return id;
+}
+
 class Session {
     constructor(session_id) {
-        this.socket = new WebSocket("ws://" + location.host + "/api/open_session/" + session_id);
+        let query = session_query(session_id);
+        query.set("producer", producer_id());
+        this.socket = new WebSocket("ws://" + location.host + "/api/open_session/" + session_id + "?" + query);
 
         this.socket.addEventListener("message", (event) => {
-            this.data = JSON.parse(event.data)
+            let json = JSON.parse(event.data);
+            if (json.Rejected != null) {
+                console.error(json.Rejected);
+                return;
+            }
+            this.data = json
         })
 
         this.socket.addEventListener("close", (event) => {
@@ -189,4 +296,28 @@ class Session {
     data() {
         return this.data;
     }
-}
\ No newline at end of file
+}
+// receives every item of a session, unlike AutoFillSession which takes one item off the queue
+class SessionSubscriber {
+    constructor(session_id, consumer, on_item, on_live) {
+        let query = session_query(session_id);
+        if (consumer != null) {
+            query.set("consumer", consumer);
+        }
+        this.socket = new WebSocket("ws://" + location.host + "/api/subscribe/" + session_id + "?" + query);
+
+        this.socket.addEventListener("message", async (event) => {
+            let json = JSON.parse(event.data);
+            if (json.Delivered != null) {
+                await on_item(json.Delivered.item);
+                this.socket.send(JSON.stringify({ "Ack": json.Delivered.seq }));
+            } else if (json.SessionLive != null && on_live != null) {
+                on_live(json.SessionLive);
+            }
+        })
+    }
+
+    close() {
+        this.socket.close()
+    }
+}